@@ -0,0 +1,438 @@
+//! Text analysis: turning a haystack entry or needle into the tokens the vectorizer counts.
+//!
+//! Analysis runs in two pluggable stages, combined by an [`Analyzer`]:
+//! * a [`Normalizer`] rewrites the text and then each of its tokens, e.g. case folding and Unicode
+//!   normalization;
+//! * a [`Tokenizer`] splits the normalized text into tokens, each of which becomes a feature.
+//!
+//! The defaults are configured from a [`MatcherConfig`] and reproduce the analysis of 0.2. The
+//! [`UnicodeNormalizer`] (unless case-sensitive) lowercases the text, and Unicode-normalizes each
+//! token once the text is tokenized. The tokenizer depends on the [`Tokenization`] mode:
+//! * [`CharNgramTokenizer`] splits the text into words on whitespace, joins the words into one
+//!   character sequence, optionally padded with a boundary marker (`_word1_word2_`), and emits every
+//!   character n-gram of that sequence, for each length in the configured range. N-grams that would
+//!   span two words are skipped unless the configuration allows them.
+//! * [`WordTokenizer`] emits whole words.
+//! * [`HybridTokenizer`] emits both, weighting the words relative to the n-grams.
+
+use crate::MatcherConfig;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization applied to each token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NormalizationForm {
+    /// Leave the text as is.
+    None,
+    /// Canonical composition.
+    Nfc,
+    /// Canonical decomposition.
+    Nfd,
+    /// Compatibility composition.
+    Nfkc,
+    /// Compatibility decomposition, e.g. `ﬁ` becomes `fi` and `é` becomes `e` plus a combining
+    /// accent.
+    #[default]
+    Nfkd,
+}
+
+impl NormalizationForm {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::None => text.to_owned(),
+            Self::Nfc => text.nfc().collect(),
+            Self::Nfd => text.nfd().collect(),
+            Self::Nfkc => text.nfkc().collect(),
+            Self::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+/// How word boundaries are represented in n-grams.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Boundary {
+    /// Pad every word with this marker on both sides, so n-grams at the start and end of a word
+    /// are distinct from those in its middle (`"ab"` yields the bigrams `_a ab b_`).
+    Marker(char),
+    /// No padding: only n-grams inside words (or across them, if allowed) are produced, and words
+    /// shorter than the n-gram length produce none.
+    None,
+}
+
+impl Default for Boundary {
+    fn default() -> Self {
+        Self::Marker('_')
+    }
+}
+
+/// Rewrites text before it is tokenized, and optionally each token after.
+///
+/// Haystack entries and needles go through the same normalizer, so anything it folds together
+/// (case, accents, punctuation) compares equal. Closures `Fn(&str) -> String` implement it.
+pub trait Normalizer: Send + Sync {
+    /// Returns the normalized form of `text`.
+    fn normalize(&self, text: &str) -> String;
+
+    /// Appends the normalized form of `token`, as one or more tokens, to `tokens`. Defaults to
+    /// appending `token` unchanged.
+    fn normalize_token(&self, token: String, tokens: &mut Vec<String>) {
+        tokens.push(token);
+    }
+}
+
+impl<F> Normalizer for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn normalize(&self, text: &str) -> String {
+        self(text)
+    }
+}
+
+/// Splits normalized text into tokens. Every distinct token is one feature of the TF-IDF
+/// vectors, and repeated tokens count towards its term frequency.
+///
+/// Closures `Fn(&str, &mut Vec<String>)` implement it, with every token weighted equally.
+pub trait Tokenizer: Send + Sync {
+    /// Appends the tokens of `text` to `tokens`.
+    fn tokenize(&self, text: &str, tokens: &mut Vec<String>);
+
+    /// Relative weight of a token this tokenizer produces. Each occurrence of the token adds this
+    /// much to its term frequency, on both the haystack and the needle side. Defaults to 1.
+    fn weight(&self, token: &str) -> f64 {
+        let _ = token;
+        1.0
+    }
+}
+
+impl<F> Tokenizer for F
+where
+    F: Fn(&str, &mut Vec<String>) + Send + Sync,
+{
+    fn tokenize(&self, text: &str, tokens: &mut Vec<String>) {
+        self(text, tokens);
+    }
+}
+
+/// The default [`Normalizer`]: unless case-sensitive, lowercases the text character by character,
+/// then applies a [`NormalizationForm`] to each token and lowercases it again.
+///
+/// Normalizing tokens rather than the text keeps n-gram windows over the original characters, so
+/// `ﬁnance` yields the trigram `_ﬁn`, normalized to `_fin`. A token that normalization breaks at
+/// whitespace (`´` decomposes to a space and a combining accent) is split there; tokens that
+/// already contain whitespace are kept whole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnicodeNormalizer {
+    form: NormalizationForm,
+    case_sensitive: bool,
+}
+
+impl UnicodeNormalizer {
+    #[must_use]
+    pub fn new(form: NormalizationForm, case_sensitive: bool) -> Self {
+        Self {
+            form,
+            case_sensitive,
+        }
+    }
+}
+
+impl Normalizer for UnicodeNormalizer {
+    fn normalize(&self, text: &str) -> String {
+        if self.case_sensitive {
+            text.to_owned()
+        } else {
+            text.chars().flat_map(char::to_lowercase).collect()
+        }
+    }
+
+    fn normalize_token(&self, token: String, tokens: &mut Vec<String>) {
+        let mut normalized = self.form.apply(&token);
+        if !self.case_sensitive {
+            normalized = normalized.to_lowercase();
+        }
+        if token.contains(char::is_whitespace) || !normalized.contains(char::is_whitespace) {
+            tokens.push(normalized);
+        } else {
+            tokens.extend(normalized.split_whitespace().map(str::to_owned));
+        }
+    }
+}
+
+/// The default [`Tokenizer`]: the character n-grams of the whitespace-separated words of the text,
+/// for every length in a range.
+///
+/// Words are joined by the boundary marker (or a space, without one). Unless `span_words` is set,
+/// an n-gram is skipped when a separator falls strictly inside it, or anywhere in it when there is
+/// no marker, so n-grams never straddle two words. A marker character occurring in the text also
+/// separates words, so `foo_bar` and `foo bar` yield the same n-grams.
+///
+/// N-grams of different lengths never collide, so each length contributes its own features. Their
+/// term frequencies can be weighted per length with [`with_weights`](Self::with_weights).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharNgramTokenizer {
+    ngram_range: RangeInclusive<usize>,
+    weights: Vec<f64>,
+    boundary: Boundary,
+    span_words: bool,
+}
+
+impl CharNgramTokenizer {
+    /// N-grams of every length in `ngram_range`, e.g. `3..=3` for trigrams only.
+    #[must_use]
+    pub fn new(ngram_range: RangeInclusive<usize>, boundary: Boundary, span_words: bool) -> Self {
+        Self {
+            ngram_range,
+            weights: Vec::new(),
+            boundary,
+            span_words,
+        }
+    }
+
+    /// Weights n-grams by length: `weights[i]` applies to n-grams of length
+    /// `ngram_range.start() + i`. Lengths without a weight are weighted 1.
+    #[must_use]
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        self.weights = weights;
+        self
+    }
+}
+
+impl Tokenizer for CharNgramTokenizer {
+    fn tokenize(&self, text: &str, tokens: &mut Vec<String>) {
+        let boundary = self.boundary;
+        let separator = match boundary {
+            Boundary::Marker(marker) => marker,
+            Boundary::None => ' ',
+        };
+        // Build the character sequence, e.g. `_word1_word2_`, flagging the separators we insert.
+        let mut chars: Vec<(char, bool)> = Vec::with_capacity(text.len() + 2);
+        if let Boundary::Marker(marker) = boundary {
+            chars.push((marker, true));
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            if i > 0 {
+                chars.push((separator, true));
+            }
+            chars.extend(word.chars().map(|c| (c, c == separator)));
+        }
+        if let Boundary::Marker(marker) = boundary {
+            chars.push((marker, true));
+        }
+
+        let padded = matches!(boundary, Boundary::Marker(_));
+        for n in self.ngram_range.clone() {
+            if n == 0 || chars.len() < n {
+                continue;
+            }
+            for window in chars.windows(n) {
+                let keep = self.span_words
+                    || if padded {
+                        // Skip n-grams that cross word boundaries (have a separator in the middle).
+                        n <= 2 || !window[1..n - 1].iter().any(|&(_, sep)| sep)
+                    } else {
+                        !window.iter().any(|&(_, sep)| sep)
+                    };
+                if keep {
+                    tokens.push(window.iter().map(|&(c, _)| c).collect());
+                }
+            }
+        }
+    }
+
+    fn weight(&self, token: &str) -> f64 {
+        token
+            .chars()
+            .count()
+            .checked_sub(*self.ngram_range.start())
+            .and_then(|i| self.weights.get(i))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+/// The kind of tokens the default tokenizer produces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tokenization {
+    /// Character n-grams ([`CharNgramTokenizer`]). Robust to typos and word order, but common
+    /// substrings of long strings add noise.
+    #[default]
+    CharNgrams,
+    /// Whole words ([`WordTokenizer`]). Rare words dominate the score, but misspelled words don't
+    /// match at all.
+    Words,
+    /// Both character n-grams and whole words ([`HybridTokenizer`]).
+    Hybrid,
+}
+
+/// A [`Tokenizer`] emitting whole words: the maximal runs of alphanumeric characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WordTokenizer;
+
+impl Tokenizer for WordTokenizer {
+    fn tokenize(&self, text: &str, tokens: &mut Vec<String>) {
+        tokens.extend(
+            text.split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_owned),
+        );
+    }
+}
+
+/// A [`Tokenizer`] emitting the character n-grams of a [`CharNgramTokenizer`] followed by the
+/// words of a [`WordTokenizer`], whose term frequencies are scaled by `word_weight`.
+///
+/// Word tokens are prefixed with [`WORD_PREFIX`](Self::WORD_PREFIX) so that a word can't share a
+/// feature with an n-gram spelled the same way.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HybridTokenizer {
+    chars: CharNgramTokenizer,
+    word_weight: f64,
+}
+
+impl HybridTokenizer {
+    /// Marks word tokens. Words never contain it, since it isn't alphanumeric.
+    pub const WORD_PREFIX: char = '\u{1f}';
+
+    #[must_use]
+    pub fn new(chars: CharNgramTokenizer, word_weight: f64) -> Self {
+        Self { chars, word_weight }
+    }
+}
+
+impl Tokenizer for HybridTokenizer {
+    fn tokenize(&self, text: &str, tokens: &mut Vec<String>) {
+        self.chars.tokenize(text, tokens);
+        let start = tokens.len();
+        WordTokenizer.tokenize(text, tokens);
+        for word in &mut tokens[start..] {
+            word.insert(0, Self::WORD_PREFIX);
+        }
+    }
+
+    fn weight(&self, token: &str) -> f64 {
+        if token.starts_with(Self::WORD_PREFIX) {
+            self.word_weight
+        } else {
+            self.chars.weight(token)
+        }
+    }
+}
+
+/// A [`Normalizer`] and [`Tokenizer`] pair: the complete analysis pipeline of a matcher.
+///
+/// Matchers built without custom components use [`Analyzer::from_config`]. Custom components
+/// can't be saved with an index, so a matcher built with them is loaded back with
+/// [`TFIDFMatcher::load_with`](crate::TFIDFMatcher::load_with) and the same analyzer.
+#[derive(Clone)]
+pub struct Analyzer {
+    normalizer: Arc<dyn Normalizer>,
+    tokenizer: Arc<dyn Tokenizer>,
+    custom: bool,
+}
+
+impl Analyzer {
+    /// Combines a custom normalizer and tokenizer.
+    pub fn new(normalizer: impl Normalizer + 'static, tokenizer: impl Tokenizer + 'static) -> Self {
+        Self {
+            normalizer: Arc::new(normalizer),
+            tokenizer: Arc::new(tokenizer),
+            custom: true,
+        }
+    }
+
+    /// The default pipeline for `config`: a [`UnicodeNormalizer`] followed by the tokenizer for
+    /// its [`Tokenization`] mode.
+    #[must_use]
+    pub fn from_config(config: &MatcherConfig) -> Self {
+        Self::with_parts(config, None, None)
+    }
+
+    /// The default pipeline for `config`, with either component optionally replaced.
+    pub(crate) fn with_parts(
+        config: &MatcherConfig,
+        normalizer: Option<Arc<dyn Normalizer>>,
+        tokenizer: Option<Arc<dyn Tokenizer>>,
+    ) -> Self {
+        let custom = normalizer.is_some() || tokenizer.is_some();
+        Self {
+            normalizer: normalizer.unwrap_or_else(|| {
+                Arc::new(UnicodeNormalizer::new(
+                    config.normalization,
+                    config.case_sensitive,
+                ))
+            }),
+            tokenizer: tokenizer.unwrap_or_else(|| {
+                let chars = CharNgramTokenizer::new(
+                    config.ngram_range.clone(),
+                    config.boundary,
+                    config.span_words,
+                )
+                .with_weights(config.ngram_weights.clone());
+                match config.tokenization {
+                    Tokenization::CharNgrams => Arc::new(chars),
+                    Tokenization::Words => Arc::new(WordTokenizer),
+                    Tokenization::Hybrid => {
+                        Arc::new(HybridTokenizer::new(chars, config.word_weight))
+                    }
+                }
+            }),
+            custom,
+        }
+    }
+
+    /// Replaces the normalizer.
+    #[must_use]
+    pub fn with_normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizer = Arc::new(normalizer);
+        self.custom = true;
+        self
+    }
+
+    /// Replaces the tokenizer.
+    #[must_use]
+    pub fn with_tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Arc::new(tokenizer);
+        self.custom = true;
+        self
+    }
+
+    /// Whether a custom normalizer or tokenizer is in use, i.e. the pipeline can't be rebuilt from
+    /// a saved [`MatcherConfig`].
+    #[must_use]
+    pub fn is_custom(&self) -> bool {
+        self.custom
+    }
+
+    /// Normalizes `text` and returns its normalized tokens.
+    #[must_use]
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        let mut raw = Vec::new();
+        self.tokenizer
+            .tokenize(&self.normalizer.normalize(text), &mut raw);
+        let mut tokens = Vec::with_capacity(raw.len());
+        for token in raw {
+            self.normalizer.normalize_token(token, &mut tokens);
+        }
+        tokens
+    }
+
+    /// The tokenizer's weight for `token`.
+    #[must_use]
+    pub fn weight(&self, token: &str) -> f64 {
+        self.tokenizer.weight(token)
+    }
+}
+
+impl fmt::Debug for Analyzer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Analyzer")
+            .field("custom", &self.custom)
+            .finish_non_exhaustive()
+    }
+}
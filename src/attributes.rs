@@ -0,0 +1,172 @@
+//! Filterable attributes attached to haystack entries.
+//!
+//! Each entry can carry tags ("active", "country:DE") and numeric fields ("revenue"). They are
+//! stored with the matcher, follow their entries through [`remove`](TFIDFMatcher::remove) and
+//! [`compact`](TFIDFMatcher::compact), are saved with the index, and are queried by
+//! [`Filter::tag`](crate::Filter::tag) and [`Filter::range`](crate::Filter::range) at the time of
+//! each query, so those filters never go stale.
+
+use crate::{DocSet, TFIDFMatcher};
+use std::collections::BTreeMap;
+
+/// The tags and numeric fields of every entry of a matcher.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Attributes {
+    /// The entries carrying each tag. Tags no entry carries are dropped.
+    pub(crate) tags: BTreeMap<String, DocSet>,
+    /// Each field's value by entry, NaN where unset. A field's vector may be shorter than the
+    /// haystack; entries past its end don't have the field.
+    pub(crate) fields: BTreeMap<String, Vec<f64>>,
+}
+
+impl Attributes {
+    /// The entries carrying `tag`, if any does.
+    pub(crate) fn tagged(&self, tag: &str) -> Option<&DocSet> {
+        self.tags.get(tag)
+    }
+
+    /// The values of `field` by entry, NaN where unset.
+    pub(crate) fn field(&self, field: &str) -> &[f64] {
+        self.fields.get(field).map_or(&[], Vec::as_slice)
+    }
+
+    fn number(&self, doc: usize, field: &str) -> Option<f64> {
+        self.field(field)
+            .get(doc)
+            .copied()
+            .filter(|value| !value.is_nan())
+    }
+
+    fn untag(&mut self, doc: usize, tag: &str) -> bool {
+        let Some(docs) = self.tags.get_mut(tag) else {
+            return false;
+        };
+        let removed = docs.remove(doc);
+        if docs.is_empty() {
+            self.tags.remove(tag);
+        }
+        removed
+    }
+
+    /// Clears every tag and field of `doc`.
+    pub(crate) fn clear(&mut self, doc: usize) {
+        self.tags.retain(|_, docs| {
+            docs.remove(doc);
+            !docs.is_empty()
+        });
+        for values in self.fields.values_mut() {
+            if let Some(value) = values.get_mut(doc) {
+                *value = f64::NAN;
+            }
+        }
+    }
+
+    /// Renumbers the entries after compaction: entry `new` is the one previously at `kept[new]`.
+    pub(crate) fn remap(&self, kept: &[usize]) -> Self {
+        let renumber = |docs: &DocSet| -> DocSet {
+            (0..kept.len())
+                .filter(|&new| docs.contains(kept[new]))
+                .collect()
+        };
+        Self {
+            tags: self
+                .tags
+                .iter()
+                .map(|(tag, docs)| (tag.clone(), renumber(docs)))
+                .filter(|(_, docs)| !docs.is_empty())
+                .collect(),
+            fields: self
+                .fields
+                .iter()
+                .map(|(field, values)| {
+                    let values = kept
+                        .iter()
+                        .map(|&old| values.get(old).copied().unwrap_or(f64::NAN))
+                        .collect();
+                    (field.clone(), values)
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TFIDFMatcher {
+    /// Tags the entry at `haystack_idx` with `tag`, and returns whether it is present and didn't
+    /// already carry it.
+    ///
+    /// ```
+    /// use tf_idf_matcher::{FindOptions, Filter, TFIDFMatcher};
+    ///
+    /// let mut matcher = TFIDFMatcher::new(["Acme GmbH", "Acme Ltd"], 3)?;
+    /// matcher.tag(0, "country:DE");
+    /// let options = FindOptions::new().filter(Filter::tag("country:DE"));
+    /// let result = matcher.find_with("acme ltd", 2, &options)?;
+    /// assert_eq!(result.matches.len(), 1);
+    /// assert_eq!(result.matches[0].haystack_idx, 0);
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    pub fn tag(&mut self, haystack_idx: usize, tag: impl Into<String>) -> bool {
+        if self.get(haystack_idx).is_none() {
+            return false;
+        }
+        self.attributes
+            .tags
+            .entry(tag.into())
+            .or_default()
+            .insert(haystack_idx)
+    }
+
+    /// Removes `tag` from the entry at `haystack_idx`, and returns whether it carried it.
+    pub fn untag(&mut self, haystack_idx: usize, tag: &str) -> bool {
+        self.attributes.untag(haystack_idx, tag)
+    }
+
+    /// Whether the entry at `haystack_idx` carries `tag`. Removed entries carry no tags.
+    #[must_use]
+    pub fn has_tag(&self, haystack_idx: usize, tag: &str) -> bool {
+        self.attributes
+            .tagged(tag)
+            .is_some_and(|docs| docs.contains(haystack_idx))
+    }
+
+    /// Sets the numeric `field` of the entry at `haystack_idx` to `value`, and returns whether the
+    /// entry is present.
+    ///
+    /// # Panics
+    /// Panics if `value` is NaN.
+    pub fn set_number(
+        &mut self,
+        haystack_idx: usize,
+        field: impl Into<String>,
+        value: f64,
+    ) -> bool {
+        assert!(!value.is_nan(), "field values must not be NaN");
+        if self.get(haystack_idx).is_none() {
+            return false;
+        }
+        let values = self.attributes.fields.entry(field.into()).or_default();
+        if values.len() <= haystack_idx {
+            values.resize(haystack_idx + 1, f64::NAN);
+        }
+        values[haystack_idx] = value;
+        true
+    }
+
+    /// Clears the numeric `field` of the entry at `haystack_idx`, and returns its value, if it
+    /// had one.
+    pub fn unset_number(&mut self, haystack_idx: usize, field: &str) -> Option<f64> {
+        let slot = self
+            .attributes
+            .fields
+            .get_mut(field)?
+            .get_mut(haystack_idx)?;
+        let value = std::mem::replace(slot, f64::NAN);
+        (!value.is_nan()).then_some(value)
+    }
+
+    /// The numeric `field` of the entry at `haystack_idx`, if it is set.
+    #[must_use]
+    pub fn number(&self, haystack_idx: usize, field: &str) -> Option<f64> {
+        self.attributes.number(haystack_idx, field)
+    }
+}
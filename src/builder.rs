@@ -0,0 +1,329 @@
+//! [`TFIDFMatcherBuilder`] and the [`MatcherConfig`] it assembles.
+
+use crate::analysis::{Analyzer, Boundary, NormalizationForm, Normalizer, Tokenization, Tokenizer};
+use crate::vectorizer::{IdfMethod, TfMethod};
+use crate::{MatcherError, ScoringVersion, TFIDFMatcher};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+/// Largest supported [`MatcherConfig::confidence_decimals`]; an `f64` carries no more than 15
+/// significant decimal digits.
+const MAX_CONFIDENCE_DECIMALS: u32 = 15;
+
+/// Every setting that shapes how a [`TFIDFMatcher`] analyzes text and scores matches.
+///
+/// Assembled and validated by [`TFIDFMatcherBuilder`]; a built matcher exposes its configuration
+/// through [`TFIDFMatcher::config`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[non_exhaustive]
+pub struct MatcherConfig {
+    /// Whether the default tokenizer emits character n-grams, whole words or both.
+    pub tokenization: Tokenization,
+    /// In [`Tokenization::Hybrid`] mode, the weight of word term frequencies relative to n-gram
+    /// ones. Raise it to let rare whole words, like a brand name, dominate the score.
+    pub word_weight: f64,
+    /// Lengths of the character n-grams, e.g. `3..=3` for trigrams only or `2..=4` for bigrams,
+    /// trigrams and 4-grams together. Like the other analysis settings below, it is ignored once a
+    /// custom [`Tokenizer`] replaces the default one.
+    pub ngram_range: RangeInclusive<usize>,
+    /// Per-length weights of the n-gram term frequencies, starting with the shortest length in
+    /// `ngram_range`. Empty to weight every length equally.
+    pub ngram_weights: Vec<f64>,
+    /// Whether case is significant. When `false`, text is lowercased before n-grams are taken.
+    pub case_sensitive: bool,
+    /// Unicode normalization applied to text before n-grams are taken.
+    pub normalization: NormalizationForm,
+    /// How word boundaries are represented.
+    pub boundary: Boundary,
+    /// Whether n-grams may span two words.
+    pub span_words: bool,
+    /// How inverse document frequencies are computed.
+    pub idf: IdfMethod,
+    /// How term counts are turned into term frequencies.
+    pub tf: TfMethod,
+    /// Decimal places [`MatchEntry::confidence`](crate::MatchEntry::confidence) is rounded to, or
+    /// `None` to report it unrounded. [`MatchEntry::score`](crate::MatchEntry::score) is never
+    /// rounded.
+    pub confidence_decimals: Option<u32>,
+    /// How needles are weighted.
+    pub scoring: ScoringVersion,
+}
+
+impl Default for MatcherConfig {
+    fn default() -> Self {
+        Self {
+            tokenization: Tokenization::default(),
+            word_weight: 1.0,
+            ngram_range: 3..=3,
+            ngram_weights: Vec::new(),
+            case_sensitive: false,
+            normalization: NormalizationForm::default(),
+            boundary: Boundary::default(),
+            span_words: false,
+            idf: IdfMethod::default(),
+            tf: TfMethod::default(),
+            confidence_decimals: Some(2),
+            scoring: ScoringVersion::default(),
+        }
+    }
+}
+
+impl MatcherConfig {
+    /// Checks that the settings are usable together.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] describing the first problem found.
+    pub fn validate(&self) -> Result<(), MatcherError> {
+        let (min, max) = (*self.ngram_range.start(), *self.ngram_range.end());
+        if min == 0 {
+            return Err(MatcherError::InvalidConfig(
+                "n-gram lengths must be at least 1".to_owned(),
+            ));
+        }
+        if min > max {
+            return Err(MatcherError::InvalidConfig(format!(
+                "n-gram range {min}..={max} is empty"
+            )));
+        }
+        if !self.ngram_weights.is_empty() && self.ngram_weights.len() != max - min + 1 {
+            return Err(MatcherError::InvalidConfig(format!(
+                "expected {} n-gram weights for lengths {min}..={max}, got {}",
+                max - min + 1,
+                self.ngram_weights.len()
+            )));
+        }
+        if let Some(weight) = self
+            .ngram_weights
+            .iter()
+            .find(|weight| !(weight.is_finite() && **weight > 0.0))
+        {
+            return Err(MatcherError::InvalidConfig(format!(
+                "n-gram weights must be positive and finite, got {weight}"
+            )));
+        }
+        if !(self.word_weight.is_finite() && self.word_weight > 0.0) {
+            return Err(MatcherError::InvalidConfig(format!(
+                "word_weight must be positive and finite, got {}",
+                self.word_weight
+            )));
+        }
+        if let TfMethod::Bm25 { k1, b } = self.tf
+            && !(k1.is_finite() && k1 >= 0.0 && (0.0..=1.0).contains(&b))
+        {
+            return Err(MatcherError::InvalidConfig(format!(
+                "BM25 needs a finite k1 >= 0 and b between 0 and 1, got k1 = {k1}, b = {b}"
+            )));
+        }
+        if let Some(decimals) = self.confidence_decimals
+            && decimals > MAX_CONFIDENCE_DECIMALS
+        {
+            return Err(MatcherError::InvalidConfig(format!(
+                "confidence_decimals must be at most {MAX_CONFIDENCE_DECIMALS}, got {decimals}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Rounds a similarity score for presentation, per `confidence_decimals`.
+    pub(crate) fn round_confidence(&self, sim: f64) -> f64 {
+        match self.confidence_decimals {
+            Some(decimals) => {
+                #[allow(clippy::cast_possible_wrap)] // bounded by MAX_CONFIDENCE_DECIMALS
+                let scale = 10f64.powi(decimals as i32);
+                (sim * scale).round() / scale
+            }
+            None => sim,
+        }
+    }
+}
+
+/// Builder for a [`TFIDFMatcher`] with a non-default analysis pipeline or scoring.
+///
+/// ```
+/// use tf_idf_matcher::{Boundary, TFIDFMatcher};
+///
+/// let matcher = TFIDFMatcher::builder()
+///     .ngram_length(2)
+///     .case_sensitive(true)
+///     .boundary(Boundary::Marker('#'))
+///     .build(["Rust", "rust"])?;
+/// assert_eq!(matcher.find("Rust", 1)?.matches[0].haystack_idx, 0);
+/// # Ok::<(), tf_idf_matcher::MatcherError>(())
+/// ```
+#[derive(Clone, Default)]
+#[must_use]
+pub struct TFIDFMatcherBuilder {
+    config: MatcherConfig,
+    normalizer: Option<Arc<dyn Normalizer>>,
+    tokenizer: Option<Arc<dyn Tokenizer>>,
+}
+
+impl fmt::Debug for TFIDFMatcherBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TFIDFMatcherBuilder")
+            .field("config", &self.config)
+            .field("custom_normalizer", &self.normalizer.is_some())
+            .field("custom_tokenizer", &self.tokenizer.is_some())
+            .finish()
+    }
+}
+
+impl TFIDFMatcherBuilder {
+    /// Starts from the default configuration (see [`MatcherConfig`]).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts from an existing configuration, e.g. that of another matcher.
+    pub fn from_config(config: MatcherConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the kind of tokens the default tokenizer emits (default [`Tokenization::CharNgrams`]).
+    pub fn tokenization(mut self, tokenization: Tokenization) -> Self {
+        self.config.tokenization = tokenization;
+        self
+    }
+
+    /// Sets the weight of whole words relative to n-grams in [`Tokenization::Hybrid`] mode
+    /// (default 1).
+    pub fn word_weight(mut self, word_weight: f64) -> Self {
+        self.config.word_weight = word_weight;
+        self
+    }
+
+    /// Uses n-grams of a single length (default 3).
+    pub fn ngram_length(mut self, ngram_length: usize) -> Self {
+        self.config.ngram_range = ngram_length..=ngram_length;
+        self.config.ngram_weights.clear();
+        self
+    }
+
+    /// Uses n-grams of every length in `ngram_range`.
+    pub fn ngram_range(mut self, ngram_range: RangeInclusive<usize>) -> Self {
+        self.config.ngram_range = ngram_range;
+        self
+    }
+
+    /// Weights the term frequencies of each n-gram length, starting with the shortest one in the
+    /// range (default: all 1). There must be one weight per length.
+    pub fn ngram_weights(mut self, weights: impl Into<Vec<f64>>) -> Self {
+        self.config.ngram_weights = weights.into();
+        self
+    }
+
+    /// Sets whether case is significant (default `false`).
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.config.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Sets the Unicode normalization form (default [`NormalizationForm::Nfkd`]).
+    pub fn normalization(mut self, normalization: NormalizationForm) -> Self {
+        self.config.normalization = normalization;
+        self
+    }
+
+    /// Sets how word boundaries are represented (default `Boundary::Marker('_')`).
+    pub fn boundary(mut self, boundary: Boundary) -> Self {
+        self.config.boundary = boundary;
+        self
+    }
+
+    /// Sets whether n-grams may span two words (default `false`).
+    pub fn span_words(mut self, span_words: bool) -> Self {
+        self.config.span_words = span_words;
+        self
+    }
+
+    /// Sets the IDF method (default [`IdfMethod::Smooth`]).
+    pub fn idf(mut self, idf: IdfMethod) -> Self {
+        self.config.idf = idf;
+        self
+    }
+
+    /// Sets the term-frequency method (default [`TfMethod::Raw`]).
+    pub fn tf(mut self, tf: TfMethod) -> Self {
+        self.config.tf = tf;
+        self
+    }
+
+    /// Weights features with BM25: [`TfMethod::Bm25`] with the given `k1` and `b`, and
+    /// [`IdfMethod::Bm25`].
+    pub fn bm25(self, k1: f64, b: f64) -> Self {
+        self.tf(TfMethod::Bm25 { k1, b }).idf(IdfMethod::Bm25)
+    }
+
+    /// Sets how many decimal places confidences are rounded to, or `None` to leave them unrounded
+    /// (default `Some(2)`).
+    pub fn confidence_decimals(mut self, decimals: Option<u32>) -> Self {
+        self.config.confidence_decimals = decimals;
+        self
+    }
+
+    /// Sets the [`ScoringVersion`] (default [`ScoringVersion::V2`]).
+    pub fn scoring_version(mut self, scoring: ScoringVersion) -> Self {
+        self.config.scoring = scoring;
+        self
+    }
+
+    /// Replaces the default [`UnicodeNormalizer`](crate::UnicodeNormalizer), making the
+    /// `normalization` and `case_sensitive` settings moot.
+    pub fn normalizer(mut self, normalizer: impl Normalizer + 'static) -> Self {
+        self.normalizer = Some(Arc::new(normalizer));
+        self
+    }
+
+    /// Replaces the default tokenizer, making the `tokenization`, `word_weight`, `ngram_range`,
+    /// `ngram_weights`, `boundary` and `span_words` settings moot.
+    pub fn tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Some(Arc::new(tokenizer));
+        self
+    }
+
+    /// The analysis pipeline the built matcher will use.
+    #[must_use]
+    pub fn analyzer(&self) -> Analyzer {
+        Analyzer::with_parts(
+            &self.config,
+            self.normalizer.clone(),
+            self.tokenizer.clone(),
+        )
+    }
+
+    /// The configuration assembled so far.
+    #[must_use]
+    pub fn config(&self) -> &MatcherConfig {
+        &self.config
+    }
+
+    /// Validates the configuration and builds a matcher over `haystack`.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if the configuration is invalid (see
+    /// [`MatcherConfig::validate`]).
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn build<T>(
+        &self,
+        haystack: impl IntoIterator<Item = T>,
+    ) -> Result<TFIDFMatcher, MatcherError>
+    where
+        T: Into<String>,
+    {
+        self.config.validate()?;
+        Ok(TFIDFMatcher::build(
+            haystack.into_iter().map(Into::into).collect(),
+            self.config.clone(),
+            self.analyzer(),
+        ))
+    }
+}
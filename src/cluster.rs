@@ -0,0 +1,373 @@
+//! Grouping near-duplicate haystack entries into clusters.
+//!
+//! Clusters are built from the pairs found by [`TFIDFMatcher::self_join`]. Single linkage takes
+//! the connected components of the pairs, joined with a union-find. The stricter linkages merge
+//! clusters in descending order of their strongest pair, and only while the linkage condition holds
+//! across every pair of members; pairs the self-join didn't report count as similarity 0. The
+//! reported pairs between clusters are tallied as clusters merge, so judging a merge never visits
+//! the members' pairs one by one.
+
+use crate::TFIDFMatcher;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+/// How the similarity between two clusters is judged when deciding whether to merge them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Linkage {
+    /// Clusters merge if any member of one is similar to any member of the other: clusters are the
+    /// connected components of the similar pairs. Chains of small differences can join dissimilar
+    /// entries.
+    #[default]
+    Single,
+    /// Clusters merge only if every member of one is similar to every member of the other.
+    Complete,
+    /// Clusters merge if the similarity averaged over all pairs of their members reaches the
+    /// threshold.
+    Average,
+}
+
+/// Picks a cluster's canonical member from its members; see [`Canonical::Custom`].
+pub type ChooseCanonical = dyn Fn(&[usize]) -> usize + Send + Sync;
+
+/// How each cluster's canonical member is chosen.
+#[derive(Clone, Default)]
+pub enum Canonical {
+    /// The member with the highest total similarity to the other members.
+    #[default]
+    Central,
+    /// The member with the longest text, in characters.
+    Longest,
+    /// The member the function picks from the cluster's members, given in ascending index order.
+    /// It must return one of them.
+    Custom(Arc<ChooseCanonical>),
+}
+
+impl Canonical {
+    /// The member `choose` picks from a cluster's members; see [`Canonical::Custom`].
+    pub fn custom(choose: impl Fn(&[usize]) -> usize + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(choose))
+    }
+}
+
+impl fmt::Debug for Canonical {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Central => f.write_str("Central"),
+            Self::Longest => f.write_str("Longest"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// Options for [`TFIDFMatcher::cluster`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+#[must_use]
+pub struct ClusterOptions {
+    /// How clusters are merged.
+    pub linkage: Linkage,
+    /// How each cluster's canonical member is chosen.
+    pub canonical: Canonical,
+    /// How many of each entry's best matches are considered, as in
+    /// [`TFIDFMatcher::self_join`]. Unbounded by default.
+    pub top_k: usize,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            linkage: Linkage::default(),
+            canonical: Canonical::default(),
+            top_k: usize::MAX,
+        }
+    }
+}
+
+impl ClusterOptions {
+    /// The default options: single linkage, the most central member as canonical, every match
+    /// considered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how clusters are merged.
+    pub fn linkage(mut self, linkage: Linkage) -> Self {
+        self.linkage = linkage;
+        self
+    }
+
+    /// Sets how each cluster's canonical member is chosen.
+    pub fn canonical(mut self, canonical: Canonical) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Sets how many of each entry's best matches are considered.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+}
+
+/// A group of haystack entries considered the same.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Cluster {
+    /// The members' haystack indices, ascending.
+    pub members: Vec<usize>,
+    /// The haystack index of the member representing the cluster.
+    pub canonical: usize,
+}
+
+/// The result of [`TFIDFMatcher::cluster`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Clustering {
+    /// `assignments[haystack_idx]` is the ID of the entry's cluster, an index into `clusters`, or
+    /// `None` if the entry was removed.
+    pub assignments: Vec<Option<usize>>,
+    /// Every cluster, singletons included, numbered in order of their lowest member.
+    pub clusters: Vec<Cluster>,
+}
+
+/// A union-find over haystack indices, with path halving and union by size.
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    /// Joins the sets of roots `a` and `b` and returns the new root.
+    fn union(&mut self, a: usize, b: usize) -> usize {
+        let (root, child) = if self.size[a] >= self.size[b] {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        self.parent[child] = root;
+        self.size[root] += self.size[child];
+        root
+    }
+}
+
+/// The reported pairs between the members of two clusters: how many there are and their total
+/// similarity.
+#[derive(Debug, Clone, Copy, Default)]
+struct Link {
+    count: usize,
+    total: f64,
+}
+
+impl Link {
+    fn add(&mut self, other: Self) {
+        self.count += other.count;
+        self.total += other.total;
+    }
+}
+
+/// The [`Link`]s between clusters, updated as they merge. Each cluster's links live in a slot;
+/// merging keeps the slot with more links and moves the other's into it, so every link moves
+/// O(log n) times.
+struct Links {
+    /// `slot[root]` is the slot of the cluster rooted at `root`.
+    slot: Vec<usize>,
+    /// The links of each slot's cluster, keyed by the other cluster's slot.
+    links: Vec<HashMap<usize, Link>>,
+}
+
+impl Links {
+    /// One singleton cluster per entry, linked by `pairs`.
+    fn new(n: usize, pairs: &[(usize, usize, f64)]) -> Self {
+        let mut links = vec![HashMap::new(); n];
+        for &(i, j, score) in pairs {
+            let link = Link {
+                count: 1,
+                total: score,
+            };
+            links[i].insert(j, link);
+            links[j].insert(i, link);
+        }
+        Self {
+            slot: (0..n).collect(),
+            links,
+        }
+    }
+
+    /// The link between the clusters rooted at `a` and `b`.
+    fn between(&self, a: usize, b: usize) -> Link {
+        self.links[self.slot[a]]
+            .get(&self.slot[b])
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Records that the clusters rooted at `a` and `b` merged into the one rooted at `root`.
+    fn merge(&mut self, a: usize, b: usize, root: usize) {
+        let (slot_a, slot_b) = (self.slot[a], self.slot[b]);
+        let (keep, moved) = if self.links[slot_a].len() >= self.links[slot_b].len() {
+            (slot_a, slot_b)
+        } else {
+            (slot_b, slot_a)
+        };
+        let moved_links = std::mem::take(&mut self.links[moved]);
+        self.links[keep].remove(&moved);
+        for (other, link) in moved_links {
+            if other == keep {
+                continue;
+            }
+            self.links[keep].entry(other).or_default().add(link);
+            let theirs = &mut self.links[other];
+            theirs.remove(&moved);
+            theirs.entry(keep).or_default().add(link);
+        }
+        self.slot[root] = keep;
+    }
+}
+
+impl Linkage {
+    /// Whether clusters of `a_len` and `b_len` members joined by `link` may merge. The self-join
+    /// reports only pairs at or above `threshold`, and at a threshold of 0 or below the pairs it
+    /// didn't report pass too.
+    // Cluster sizes are far below 2^52; the cast is exact in practice.
+    #[allow(clippy::cast_precision_loss)]
+    fn links(self, a_len: usize, b_len: usize, link: Link, threshold: f64) -> bool {
+        match self {
+            Self::Single => true,
+            Self::Complete => threshold <= 0.0 || link.count == a_len * b_len,
+            Self::Average => link.total / (a_len * b_len) as f64 >= threshold,
+        }
+    }
+}
+
+impl TFIDFMatcher {
+    /// Groups the haystack into clusters of entries similar at `threshold` or above, as judged by
+    /// `options.linkage`, and picks a canonical member for each.
+    ///
+    /// Every entry not removed belongs to exactly one cluster, possibly on its own.
+    ///
+    /// ```
+    /// use tf_idf_matcher::{ClusterOptions, TFIDFMatcher};
+    ///
+    /// let matcher = TFIDFMatcher::new(["Acme Corp", "Globex", "ACME Corp.", "Acme Corp"], 3)?;
+    /// let clustering = matcher.cluster(0.6, &ClusterOptions::new());
+    /// assert_eq!(clustering.assignments, [Some(0), Some(1), Some(0), Some(0)]);
+    /// assert_eq!(clustering.clusters[0].members, [0, 2, 3]);
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if a [`Canonical::Custom`] function returns an index that isn't a member of the
+    /// cluster.
+    #[must_use]
+    pub fn cluster(&self, threshold: f64, options: &ClusterOptions) -> Clustering {
+        let mut pairs = self.self_join(threshold, options.top_k);
+        let mut sets = DisjointSets::new(self.n_docs);
+
+        if options.linkage == Linkage::Single {
+            for &(i, j, _) in &pairs {
+                let (a, b) = (sets.find(i), sets.find(j));
+                if a != b {
+                    sets.union(a, b);
+                }
+            }
+        } else {
+            let mut links = Links::new(self.n_docs, &pairs);
+            // Strongest pairs first; ties in index order, as `pairs` is sorted.
+            pairs.sort_by(|x, y| y.2.total_cmp(&x.2));
+            for &(i, j, _) in &pairs {
+                let (a, b) = (sets.find(i), sets.find(j));
+                if a == b
+                    || !options.linkage.links(
+                        sets.size[a],
+                        sets.size[b],
+                        links.between(a, b),
+                        threshold,
+                    )
+                {
+                    continue;
+                }
+                let root = sets.union(a, b);
+                links.merge(a, b, root);
+            }
+        }
+
+        // Each member's total similarity to the rest of its cluster, for `Canonical::Central`.
+        let mut centrality = vec![0.0; self.n_docs];
+        for &(i, j, score) in &pairs {
+            if sets.find(i) == sets.find(j) {
+                centrality[i] += score;
+                centrality[j] += score;
+            }
+        }
+
+        let mut assignments = vec![None; self.n_docs];
+        let mut cluster_of_root: Vec<Option<usize>> = vec![None; self.n_docs];
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        for doc in (0..self.n_docs).filter(|&doc| !self.removed[doc]) {
+            let root = sets.find(doc);
+            let id = *cluster_of_root[root].get_or_insert_with(|| {
+                clusters.push(Vec::new());
+                clusters.len() - 1
+            });
+            clusters[id].push(doc);
+            assignments[doc] = Some(id);
+        }
+
+        let clusters = clusters
+            .into_iter()
+            .map(|members| Cluster {
+                canonical: self.canonical(&members, &options.canonical, &centrality),
+                members,
+            })
+            .collect();
+        Clustering {
+            assignments,
+            clusters,
+        }
+    }
+
+    /// The canonical member of a cluster with ascending `members`; ties go to the lowest index.
+    fn canonical(&self, members: &[usize], canonical: &Canonical, centrality: &[f64]) -> usize {
+        let best_by = |key: &dyn Fn(usize) -> f64| {
+            let mut best = (members[0], key(members[0]));
+            for &doc in &members[1..] {
+                let value = key(doc);
+                if value > best.1 {
+                    best = (doc, value);
+                }
+            }
+            best.0
+        };
+        match canonical {
+            Canonical::Central => best_by(&|doc| centrality[doc]),
+            // Lengths are far below 2^52; the cast is exact in practice.
+            #[allow(clippy::cast_precision_loss)]
+            Canonical::Longest => best_by(&|doc| self.haystack[doc].chars().count() as f64),
+            Canonical::Custom(choose) => {
+                let chosen = choose(members);
+                assert!(
+                    members.binary_search(&chosen).is_ok(),
+                    "canonical member {chosen} is not in the cluster"
+                );
+                chosen
+            }
+        }
+    }
+}
@@ -0,0 +1,316 @@
+//! Restricting queries to a subset of the haystack.
+//!
+//! A [`Filter`] passed in [`FindOptions`](crate::FindOptions) is checked for every candidate
+//! document before it is ranked, so filtered-out documents never take a top-k slot.
+//!
+//! Tag and range filters are looked up in the matcher's attributes once per query, so they follow
+//! inserts, removals and compaction. A [`DocSet`] is a snapshot of haystack indices: it doesn't
+//! cover entries inserted after it was built, and goes stale when compaction renumbers them.
+
+use crate::attributes::Attributes;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, Not};
+use std::sync::Arc;
+
+/// A set of haystack indices, stored as a bitset: one bit per index up to the largest inserted.
+///
+/// Build it once and reuse it across queries, but rebuild it after compacting the matcher. For
+/// attributes that change with the haystack, tag the entries and filter with [`Filter::tag`]
+/// instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct DocSet {
+    words: Vec<u64>,
+}
+
+impl DocSet {
+    /// An empty set.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `haystack_idx`, returning whether it was newly added.
+    pub fn insert(&mut self, haystack_idx: usize) -> bool {
+        let (word, bit) = (haystack_idx / 64, 1 << (haystack_idx % 64));
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        let added = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        added
+    }
+
+    /// Removes `haystack_idx`, returning whether it was present.
+    pub fn remove(&mut self, haystack_idx: usize) -> bool {
+        let present = self.contains(haystack_idx);
+        if present {
+            self.words[haystack_idx / 64] &= !(1 << (haystack_idx % 64));
+            // Keep no trailing empty words, so equal sets are stored identically.
+            while self.words.last() == Some(&0) {
+                self.words.pop();
+            }
+        }
+        present
+    }
+
+    /// Whether `haystack_idx` is in the set.
+    #[inline]
+    #[must_use]
+    pub fn contains(&self, haystack_idx: usize) -> bool {
+        self.words
+            .get(haystack_idx / 64)
+            .is_some_and(|word| word & (1 << (haystack_idx % 64)) != 0)
+    }
+
+    /// Number of indices in the set.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Whether the set is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    /// The bitset words, bit `i % 64` of word `i / 64` standing for index `i`.
+    pub(crate) fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// The set whose bitset words are `words`.
+    pub(crate) fn from_words(mut words: Vec<u64>) -> Self {
+        while words.last() == Some(&0) {
+            words.pop();
+        }
+        Self { words }
+    }
+}
+
+impl FromIterator<usize> for DocSet {
+    fn from_iter<I: IntoIterator<Item = usize>>(iter: I) -> Self {
+        let mut set = Self::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<usize> for DocSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        for haystack_idx in iter {
+            self.insert(haystack_idx);
+        }
+    }
+}
+
+/// Which haystack entries a query may match.
+///
+/// ```
+/// use tf_idf_matcher::{FindOptions, Filter, TFIDFMatcher};
+///
+/// let mut matcher = TFIDFMatcher::new(["Acme GmbH", "Acme AG", "Acme Ltd"], 3)?;
+/// matcher.tag(0, "active");
+/// matcher.tag(1, "active");
+/// matcher.set_number(0, "revenue", 2.5e6);
+/// matcher.set_number(1, "revenue", 4.0e5);
+/// let filter = Filter::tag("active") & Filter::range("revenue", 1e6, f64::INFINITY);
+/// let result = matcher.find_with("acme", 3, &FindOptions::new().filter(filter))?;
+/// assert_eq!(result.matches.len(), 1);
+/// assert_eq!(result.matches[0].haystack_idx, 0);
+/// # Ok::<(), tf_idf_matcher::MatcherError>(())
+/// ```
+#[derive(Clone)]
+#[non_exhaustive]
+pub enum Filter {
+    /// Only the entries in the set.
+    Allow(Arc<DocSet>),
+    /// Every entry except those in the set.
+    Deny(Arc<DocSet>),
+    /// Only the entries whose `haystack_idx` the predicate accepts.
+    Predicate(Arc<dyn Fn(usize) -> bool + Send + Sync>),
+    /// Only the entries carrying the tag (see [`TFIDFMatcher::tag`](crate::TFIDFMatcher::tag)).
+    Tag(String),
+    /// Only the entries whose numeric field lies in `min..=max` (see
+    /// [`TFIDFMatcher::set_number`](crate::TFIDFMatcher::set_number)). Entries without the field
+    /// are excluded.
+    Range {
+        /// The numeric field compared.
+        field: String,
+        /// Lowest admitted value.
+        min: f64,
+        /// Highest admitted value.
+        max: f64,
+    },
+    /// Only the entries every filter admits.
+    All(Vec<Filter>),
+    /// Only the entries at least one filter admits.
+    Any(Vec<Filter>),
+    /// Only the entries the filter rejects.
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Only the entries in `set`.
+    pub fn allow(set: impl Into<Arc<DocSet>>) -> Self {
+        Self::Allow(set.into())
+    }
+
+    /// Every entry except those in `set`.
+    pub fn deny(set: impl Into<Arc<DocSet>>) -> Self {
+        Self::Deny(set.into())
+    }
+
+    /// Only the entries whose `haystack_idx` `predicate` accepts. The predicate runs once per
+    /// candidate, so keep it cheap; precompile a [`DocSet`] for filters reused across queries.
+    pub fn predicate(predicate: impl Fn(usize) -> bool + Send + Sync + 'static) -> Self {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    /// Only the entries carrying `tag`.
+    pub fn tag(tag: impl Into<String>) -> Self {
+        Self::Tag(tag.into())
+    }
+
+    /// Only the entries whose numeric `field` lies in `min..=max`. Use infinite bounds for
+    /// one-sided ranges.
+    pub fn range(field: impl Into<String>, min: f64, max: f64) -> Self {
+        Self::Range {
+            field: field.into(),
+            min,
+            max,
+        }
+    }
+
+    /// Looks the filter's tags and fields up in `attributes`, once per query.
+    pub(crate) fn resolve<'f>(&'f self, attributes: &'f Attributes) -> Resolved<'f> {
+        static EMPTY: DocSet = DocSet { words: Vec::new() };
+        let all = |filters: &'f [Filter]| {
+            filters
+                .iter()
+                .map(|filter| filter.resolve(attributes))
+                .collect()
+        };
+        match self {
+            Self::Allow(set) => Resolved::Set(set, true),
+            Self::Deny(set) => Resolved::Set(set, false),
+            Self::Predicate(predicate) => Resolved::Predicate(predicate.as_ref()),
+            Self::Tag(tag) => Resolved::Set(attributes.tagged(tag).unwrap_or(&EMPTY), true),
+            Self::Range { field, min, max } => Resolved::Range(attributes.field(field), *min, *max),
+            Self::All(filters) => Resolved::All(all(filters)),
+            Self::Any(filters) => Resolved::Any(all(filters)),
+            Self::Not(filter) => Resolved::Not(Box::new(filter.resolve(attributes))),
+        }
+    }
+}
+
+/// Both filters: `a & b` is `Filter::All(vec![a, b])`.
+impl BitAnd for Filter {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        match self {
+            Self::All(mut filters) => {
+                filters.push(other);
+                Self::All(filters)
+            }
+            _ => Self::All(vec![self, other]),
+        }
+    }
+}
+
+/// Either filter: `a | b` is `Filter::Any(vec![a, b])`.
+impl BitOr for Filter {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        match self {
+            Self::Any(mut filters) => {
+                filters.push(other);
+                Self::Any(filters)
+            }
+            _ => Self::Any(vec![self, other]),
+        }
+    }
+}
+
+impl Not for Filter {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::Not(Box::new(self))
+    }
+}
+
+/// A [`Filter`] with its tags and fields looked up, checked for every candidate.
+pub(crate) enum Resolved<'f> {
+    /// The set, and whether it lists the admitted entries (or the rejected ones).
+    Set(&'f DocSet, bool),
+    Predicate(&'f (dyn Fn(usize) -> bool + Send + Sync)),
+    /// A field's values by entry, and the admitted bounds.
+    Range(&'f [f64], f64, f64),
+    All(Vec<Resolved<'f>>),
+    Any(Vec<Resolved<'f>>),
+    Not(Box<Resolved<'f>>),
+}
+
+impl Resolved<'_> {
+    /// Whether the entry at `haystack_idx` passes the filter.
+    pub(crate) fn admits(&self, haystack_idx: usize) -> bool {
+        match self {
+            Self::Set(set, allow) => set.contains(haystack_idx) == *allow,
+            Self::Predicate(predicate) => predicate(haystack_idx),
+            Self::Range(values, min, max) => values
+                .get(haystack_idx)
+                .is_some_and(|value| (min..=max).contains(&value)),
+            Self::All(filters) => filters.iter().all(|filter| filter.admits(haystack_idx)),
+            Self::Any(filters) => filters.iter().any(|filter| filter.admits(haystack_idx)),
+            Self::Not(filter) => !filter.admits(haystack_idx),
+        }
+    }
+}
+
+impl fmt::Debug for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Allow(set) => f.debug_tuple("Allow").field(set).finish(),
+            Self::Deny(set) => f.debug_tuple("Deny").field(set).finish(),
+            Self::Predicate(_) => f.write_str("Predicate(..)"),
+            Self::Tag(tag) => f.debug_tuple("Tag").field(tag).finish(),
+            Self::Range { field, min, max } => f
+                .debug_struct("Range")
+                .field("field", field)
+                .field("min", min)
+                .field("max", max)
+                .finish(),
+            Self::All(filters) => f.debug_tuple("All").field(filters).finish(),
+            Self::Any(filters) => f.debug_tuple("Any").field(filters).finish(),
+            Self::Not(filter) => f.debug_tuple("Not").field(filter).finish(),
+        }
+    }
+}
+
+/// Sets compare by contents; predicates are equal only if they are the same closure.
+impl PartialEq for Filter {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Allow(a), Self::Allow(b)) | (Self::Deny(a), Self::Deny(b)) => a == b,
+            (Self::Predicate(a), Self::Predicate(b)) => Arc::ptr_eq(a, b),
+            (Self::Tag(a), Self::Tag(b)) => a == b,
+            (
+                Self::Range { field, min, max },
+                Self::Range {
+                    field: other_field,
+                    min: other_min,
+                    max: other_max,
+                },
+            ) => field == other_field && min == other_min && max == other_max,
+            (Self::All(a), Self::All(b)) | (Self::Any(a), Self::Any(b)) => a == b,
+            (Self::Not(a), Self::Not(b)) => a == b,
+            _ => false,
+        }
+    }
+}
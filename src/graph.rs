@@ -0,0 +1,176 @@
+//! The k-nearest-neighbor graph of the haystack, for entity-resolution analytics.
+//!
+//! Every entry is queried with the TF-IDF vector it was indexed with, as in
+//! [`TFIDFMatcher::self_join`], so building the graph never re-analyzes the haystack text. Edges
+//! are directed: an entry's neighbors need not count it among theirs. Given the same `k` and
+//! threshold, the self-join reports exactly the graph's edges, each pair once and undirected. The graph can be collected
+//! or streamed to a writer as CSV or JSON Lines, entry by entry, without holding all edges.
+
+use crate::{FindOptions, MatcherError, TFIDFMatcher};
+use sprs::CsMat;
+use std::io::{BufWriter, Write};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The text format [`TFIDFMatcher::write_knn_graph`] writes edges in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdgeFormat {
+    /// A `source,target,score` header, then one such line per edge.
+    #[default]
+    Csv,
+    /// One `{"source":..,"target":..,"score":..}` object per line.
+    JsonLines,
+}
+
+impl EdgeFormat {
+    fn write_header<W: Write>(self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            Self::Csv => writeln!(writer, "source,target,score"),
+            Self::JsonLines => Ok(()),
+        }
+    }
+
+    fn write_edge<W: Write>(
+        self,
+        writer: &mut W,
+        (source, target, score): (usize, usize, f64),
+    ) -> std::io::Result<()> {
+        // `{}` prints the shortest representation that reads back as the same `f64`.
+        match self {
+            Self::Csv => writeln!(writer, "{source},{target},{score}"),
+            Self::JsonLines => writeln!(
+                writer,
+                r#"{{"source":{source},"target":{target},"score":{score}}}"#
+            ),
+        }
+    }
+}
+
+impl TFIDFMatcher {
+    /// The k-nearest-neighbor graph of the haystack: for every entry not removed, an edge
+    /// `(entry, neighbor, score)` to each of its up to `k` most similar other entries scoring at
+    /// least `threshold`. Edges are grouped by entry in index order, best neighbor first. Entries
+    /// sharing only zero-weight features score exactly 0 and are never neighbors, whatever the
+    /// threshold.
+    ///
+    /// Entries are compared using their indexed vectors, as in [`self_join`](Self::self_join).
+    /// `self_join(threshold, k)` reports a pair exactly when the graph has an edge between its
+    /// entries in either direction.
+    ///
+    /// ```
+    /// use tf_idf_matcher::TFIDFMatcher;
+    ///
+    /// let matcher = TFIDFMatcher::new(["Acme Corp", "Globex", "ACME Corp.", "Initech"], 3)?;
+    /// let edges = matcher.knn_graph(1, 0.5);
+    /// let pairs: Vec<_> = edges.iter().map(|&(i, j, _)| (i, j)).collect();
+    /// assert_eq!(pairs, [(0, 2), (2, 0)]);
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    #[must_use]
+    pub fn knn_graph(&self, k: usize, threshold: f64) -> Vec<(usize, usize, f64)> {
+        let rows = self.doc_vectors();
+        let options = FindOptions::new().min_confidence(threshold);
+        self.live_docs()
+            .flat_map(|doc| self.knn_edges(&rows, doc, k, &options))
+            .collect()
+    }
+
+    /// Streams the [`knn_graph`](Self::knn_graph) to `writer` in `format`, one entry's edges at a
+    /// time, and returns the number of edges written.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if writing fails.
+    pub fn write_knn_graph<W: Write>(
+        &self,
+        k: usize,
+        threshold: f64,
+        format: EdgeFormat,
+        writer: W,
+    ) -> Result<usize, MatcherError> {
+        let rows = self.doc_vectors();
+        let options = FindOptions::new().min_confidence(threshold);
+        let mut writer = BufWriter::new(writer);
+        format.write_header(&mut writer)?;
+        let mut written = 0;
+        for doc in self.live_docs() {
+            for edge in self.knn_edges(&rows, doc, k, &options) {
+                format.write_edge(&mut writer, edge)?;
+                written += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    /// The indices of the entries that haven't been removed.
+    fn live_docs(&self) -> impl Iterator<Item = usize> + use<'_> {
+        (0..self.n_docs).filter(|&doc| !self.removed[doc])
+    }
+
+    /// The out-edges of entry `doc`, best first.
+    fn knn_edges(
+        &self,
+        rows: &CsMat<f64>,
+        doc: usize,
+        k: usize,
+        options: &FindOptions,
+    ) -> Vec<(usize, usize, f64)> {
+        self.neighbors(rows, doc, k, options)
+            .map(|(neighbor, score)| (doc, neighbor, score))
+            .collect()
+    }
+}
+
+/// How many entries are queried in parallel before their edges are written out, bounding the
+/// edges held in memory while streaming.
+#[cfg(feature = "parallel")]
+const WRITE_CHUNK_SIZE: usize = 4096;
+
+#[cfg(feature = "parallel")]
+impl TFIDFMatcher {
+    /// Like [`knn_graph`](Self::knn_graph), but queries the entries across the rayon thread
+    /// pool. Edges come out in the same order.
+    #[must_use]
+    pub fn par_knn_graph(&self, k: usize, threshold: f64) -> Vec<(usize, usize, f64)> {
+        let rows = self.doc_vectors();
+        let options = FindOptions::new().min_confidence(threshold);
+        let docs: Vec<usize> = self.live_docs().collect();
+        docs.into_par_iter()
+            .flat_map_iter(|doc| self.knn_edges(&rows, doc, k, &options))
+            .collect()
+    }
+
+    /// Like [`write_knn_graph`](Self::write_knn_graph), but queries the entries across the
+    /// rayon thread pool, a chunk at a time. The output is identical.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if writing fails.
+    pub fn par_write_knn_graph<W: Write>(
+        &self,
+        k: usize,
+        threshold: f64,
+        format: EdgeFormat,
+        writer: W,
+    ) -> Result<usize, MatcherError> {
+        let rows = self.doc_vectors();
+        let options = FindOptions::new().min_confidence(threshold);
+        let docs: Vec<usize> = self.live_docs().collect();
+        let mut writer = BufWriter::new(writer);
+        format.write_header(&mut writer)?;
+        let mut written = 0;
+        for chunk in docs.chunks(WRITE_CHUNK_SIZE) {
+            let edges: Vec<Vec<(usize, usize, f64)>> = chunk
+                .par_iter()
+                .map(|&doc| self.knn_edges(&rows, doc, k, &options))
+                .collect();
+            for edge in edges.into_iter().flatten() {
+                format.write_edge(&mut writer, edge)?;
+                written += 1;
+            }
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
@@ -0,0 +1,104 @@
+//! Incremental updates to a built [`TFIDFMatcher`].
+//!
+//! Inserts and removals keep every existing `haystack_idx` stable: inserted entries are appended,
+//! and removed ones are only tombstoned. The corpus statistics (vocabulary, document frequencies,
+//! corpus size) are kept current, so new entries and needles are weighted with the IDF of the
+//! corpus as it stands. Entries already in the index keep the weights they were indexed with;
+//! [`TFIDFMatcher::compact`] drops the tombstones and re-weights everything from scratch.
+
+use crate::vectorizer::Vocabulary;
+use crate::{Normalize, TFIDFMatcher};
+
+impl TFIDFMatcher {
+    /// Appends `entry` to the haystack and returns its index.
+    ///
+    /// New n-grams extend the vocabulary. The entry is weighted with the IDF of the corpus
+    /// including it; other entries are not re-weighted until [`compact`](Self::compact).
+    ///
+    /// # Panics
+    /// Panics if the haystack would exceed `u32::MAX` entries (the inverted index stores document
+    /// indices as `u32`).
+    pub fn insert(&mut self, entry: impl Into<String>) -> usize {
+        let entry = entry.into();
+        let doc = self.n_docs;
+        let doc_id = u32::try_from(doc).expect("corpus exceeds u32");
+
+        let ngrams = self.analyzer.analyze(&entry);
+        self.fitted.add_document(&ngrams);
+        let tfidf = self
+            .fitted
+            .transform([ngrams], |token| self.analyzer.weight(token));
+        self.postings
+            .resize_with(self.fitted.n_features(), Vec::new);
+        let row = tfidf.outer_view(0).expect("transform returns one row");
+        for (feature, &weight) in row.iter() {
+            self.postings[feature].push((doc_id, weight));
+        }
+
+        self.haystack_norm.push(tfidf.normalize()[0]);
+        self.haystack_sum.push(tfidf.weight_sums()[0]);
+        self.haystack.push(entry);
+        self.removed.push(false);
+        self.n_docs += 1;
+        doc
+    }
+
+    /// Removes the entry at `haystack_idx` so it no longer appears in results, and returns whether
+    /// it was present.
+    ///
+    /// The entry is tombstoned rather than deleted, so the indices of all other entries are
+    /// unchanged. Its tags and numeric fields are cleared; its string and postings are reclaimed by
+    /// [`compact`](Self::compact).
+    pub fn remove(&mut self, haystack_idx: usize) -> bool {
+        if haystack_idx >= self.n_docs || self.removed[haystack_idx] {
+            return false;
+        }
+        let ngrams = self.analyzer.analyze(&self.haystack[haystack_idx]);
+        self.fitted.remove_document(&ngrams);
+        self.attributes.clear(haystack_idx);
+        self.removed[haystack_idx] = true;
+        true
+    }
+
+    /// The haystack entry at `haystack_idx`, or `None` if it is out of range or removed.
+    #[must_use]
+    pub fn get(&self, haystack_idx: usize) -> Option<&str> {
+        match self.removed.get(haystack_idx) {
+            Some(false) => Some(&self.haystack[haystack_idx]),
+            _ => None,
+        }
+    }
+
+    /// Number of entries that haven't been removed.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.removed.iter().filter(|&&removed| !removed).count()
+    }
+
+    /// Whether every entry has been removed (or the haystack was empty to begin with).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops removed entries and rebuilds the index over the remaining ones, re-weighting every
+    /// entry with the current corpus IDF.
+    ///
+    /// Remaining entries are renumbered consecutively in their existing order. The returned vector
+    /// holds, for each new `haystack_idx`, the index the entry had before compaction. Tags and
+    /// numeric fields move with their entries.
+    pub fn compact(&mut self) -> Vec<usize> {
+        let kept: Vec<usize> = (0..self.n_docs).filter(|&doc| !self.removed[doc]).collect();
+        let haystack = std::mem::take(&mut self.haystack);
+        let live = haystack
+            .into_iter()
+            .zip(&self.removed)
+            .filter_map(|(entry, &removed)| (!removed).then_some(entry))
+            .collect();
+        let (config, analyzer) = (self.config.clone(), self.analyzer.clone());
+        let attributes = self.attributes.remap(&kept);
+        *self = Self::build(live, config, analyzer);
+        self.attributes = attributes;
+        kept
+    }
+}
@@ -0,0 +1,95 @@
+//! Matching the haystack against itself, to find near-duplicate entries.
+//!
+//! Each entry is scored with the TF-IDF vector it was indexed with, read back from the postings
+//! rather than re-vectorized from its text.
+
+use crate::{FindOptions, Index, TFIDFMatcher};
+use sprs::{CsMat, CsVecView};
+
+impl TFIDFMatcher {
+    /// Finds pairs of similar entries within the haystack: every unordered pair `(i, j, score)`,
+    /// with `i < j`, whose cosine similarity is at least `threshold` and where `j` is among the
+    /// `top_k` best matches of `i` or vice versa.
+    ///
+    /// Each pair is reported once, entries are never paired with themselves and removed entries
+    /// are skipped. Entries sharing only zero-weight features score exactly 0 and are never
+    /// paired, whatever the threshold. Pairs are sorted by `i`, then `j`; `score` is the full-precision similarity,
+    /// the same whichever entry of the pair is queried.
+    ///
+    /// Entries are compared using their indexed vectors, weighted with the corpus IDF as under
+    /// [`ScoringVersion::V2`](crate::ScoringVersion::V2) whatever the configured version.
+    ///
+    /// ```
+    /// use tf_idf_matcher::TFIDFMatcher;
+    ///
+    /// let matcher = TFIDFMatcher::new(["Acme Corp", "Globex", "ACME Corp.", "Initech"], 3)?;
+    /// let pairs = matcher.self_join(0.7, 5);
+    /// assert_eq!(pairs.len(), 1);
+    /// assert_eq!((pairs[0].0, pairs[0].1), (0, 2));
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    #[must_use]
+    pub fn self_join(&self, threshold: f64, top_k: usize) -> Vec<(usize, usize, f64)> {
+        let rows = self.doc_vectors();
+        let options = FindOptions::new().min_confidence(threshold);
+        let mut pairs = Vec::new();
+        for doc in (0..self.n_docs).filter(|&doc| !self.removed[doc]) {
+            for other in self.neighbors(&rows, doc, top_k, &options) {
+                pairs.push((doc.min(other.0), doc.max(other.0), other.1));
+            }
+        }
+        pairs.sort_unstable_by_key(|pair| (pair.0, pair.1));
+        pairs.dedup_by_key(|pair| (pair.0, pair.1));
+        pairs
+    }
+
+    /// The up to `top_k` best `(entry, score)` matches of entry `doc` other than itself that
+    /// scored above 0, given the matrix from [`doc_vectors`](Self::doc_vectors).
+    pub(crate) fn neighbors(
+        &self,
+        rows: &CsMat<f64>,
+        doc: usize,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> impl Iterator<Item = (usize, f64)> {
+        let row: CsVecView<f64> = rows.outer_view(doc).expect("row per document");
+        // One extra slot for `doc` itself, which is dropped here.
+        self.top_k_matches(
+            row,
+            self.haystack_norm[doc],
+            self.haystack_sum[doc],
+            top_k.saturating_add(1),
+            options,
+        )
+        .into_iter()
+        // Zero scores sort last, so dropping them never frees a slot for a better match.
+        .filter(move |entry| entry.haystack_idx != doc && entry.score > 0.0)
+        .take(top_k)
+        .map(|entry| (entry.haystack_idx, entry.score))
+    }
+
+    /// The indexed TF-IDF vectors of every entry, as a doc-major matrix transposed back from the
+    /// postings.
+    pub(crate) fn doc_vectors(&self) -> CsMat<f64> {
+        let mut indptr = vec![0; self.n_docs + 1];
+        for &(doc, _) in self.postings.iter().flatten() {
+            indptr[doc as usize + 1] += 1;
+        }
+        for doc in 0..self.n_docs {
+            indptr[doc + 1] += indptr[doc];
+        }
+        let nnz = indptr[self.n_docs];
+        let (mut indices, mut data) = (vec![0; nnz], vec![0.0; nnz]);
+        let mut next = indptr.clone();
+        // Features are visited in ascending order, so every row comes out sorted.
+        for (feature, list) in self.postings.iter().enumerate() {
+            for &(doc, weight) in list {
+                let slot = &mut next[doc as usize];
+                indices[*slot] = feature;
+                data[*slot] = weight;
+                *slot += 1;
+            }
+        }
+        CsMat::new((self.n_docs, self.postings.len()), indptr, indices, data)
+    }
+}
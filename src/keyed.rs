@@ -0,0 +1,263 @@
+//! A matcher whose haystack entries carry user payloads, such as database IDs or whole records.
+//!
+//! The payloads are stored alongside the entries and move with them through inserts, removals
+//! and [`compact`](KeyedMatcher::compact), so results identify their records directly rather than
+//! through a `haystack_idx` kept in sync by hand.
+
+use crate::{
+    DocSet, FindOptions, MatchEntry, MatcherError, Needle, TFIDFMatcher, TFIDFMatcherBuilder,
+};
+
+/// A [`MatchEntry`] together with the payload of the matched entry.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[must_use]
+pub struct KeyedMatch<'a, T> {
+    /// The match itself.
+    pub entry: MatchEntry<'a>,
+    /// The payload of the matched entry.
+    pub payload: &'a T,
+}
+
+/// The matches of one needle, with their payloads.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[must_use]
+pub struct KeyedNeedle<'a, T> {
+    /// The original query string.
+    pub needle: &'a str,
+    /// The top-k matches ranked by score.
+    pub matches: Vec<KeyedMatch<'a, T>>,
+}
+
+/// A [`TFIDFMatcher`] whose entries each carry a payload of type `T`, returned with every match.
+///
+/// Built by [`TFIDFMatcher::new_with_keys`] or [`TFIDFMatcherBuilder::build_with_keys`], which
+/// take the payloads and a closure extracting the text to match from each.
+///
+/// ```
+/// use tf_idf_matcher::TFIDFMatcher;
+///
+/// struct Company { id: u64, name: String }
+///
+/// let companies = vec![
+///     Company { id: 17, name: "Apple Inc".into() },
+///     Company { id: 42, name: "Microsoft".into() },
+/// ];
+/// let matcher = TFIDFMatcher::new_with_keys(companies, 3, |c| c.name.as_str())?;
+/// let result = matcher.find("micro soft", 1)?;
+/// assert_eq!(result.matches[0].payload.id, 42);
+/// # Ok::<(), tf_idf_matcher::MatcherError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyedMatcher<T> {
+    matcher: TFIDFMatcher,
+    /// `payloads[doc]` is the payload of entry `doc`, taken out once the entry is removed.
+    payloads: Vec<Option<T>>,
+}
+
+impl TFIDFMatcher {
+    /// Creates a matcher over `items`, matching against the text `key` extracts from each and
+    /// returning the items themselves with every match.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if `ngram_length` is 0.
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn new_with_keys<T>(
+        items: impl IntoIterator<Item = T>,
+        ngram_length: usize,
+        key: impl Fn(&T) -> &str,
+    ) -> Result<KeyedMatcher<T>, MatcherError> {
+        Self::builder()
+            .ngram_length(ngram_length)
+            .build_with_keys(items, key)
+    }
+}
+
+impl TFIDFMatcherBuilder {
+    /// Validates the configuration and builds a [`KeyedMatcher`] over `items`, matching against
+    /// the text `key` extracts from each.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if the configuration is invalid (see
+    /// [`MatcherConfig::validate`](crate::MatcherConfig::validate)).
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn build_with_keys<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        key: impl Fn(&T) -> &str,
+    ) -> Result<KeyedMatcher<T>, MatcherError> {
+        let items: Vec<T> = items.into_iter().collect();
+        let matcher = self.build(items.iter().map(|item| key(item).to_owned()))?;
+        Ok(KeyedMatcher {
+            matcher,
+            payloads: items.into_iter().map(Some).collect(),
+        })
+    }
+}
+
+impl<T> KeyedMatcher<T> {
+    /// The underlying matcher, e.g. to [`save`](TFIDFMatcher::save) the index or inspect
+    /// [`features`](TFIDFMatcher::features).
+    #[must_use]
+    pub fn matcher(&self) -> &TFIDFMatcher {
+        &self.matcher
+    }
+
+    /// The payload of the entry at `haystack_idx`, or `None` if it is out of range or removed.
+    #[must_use]
+    pub fn payload(&self, haystack_idx: usize) -> Option<&T> {
+        self.payloads.get(haystack_idx)?.as_ref()
+    }
+
+    /// The text and payload of the entry at `haystack_idx`, or `None` if it is out of range or
+    /// removed.
+    #[must_use]
+    pub fn get(&self, haystack_idx: usize) -> Option<(&str, &T)> {
+        Some((self.matcher.get(haystack_idx)?, self.payload(haystack_idx)?))
+    }
+
+    /// The indices of the entries whose payload satisfies `predicate`, for a
+    /// [`Filter`](crate::Filter) restricting queries by payload attributes. The set reflects the
+    /// entries as they are now; build it again after inserting or compacting.
+    ///
+    /// ```
+    /// use tf_idf_matcher::{FindOptions, Filter, TFIDFMatcher};
+    ///
+    /// let suppliers = [("Acme GmbH", "DE"), ("Acme Ltd", "GB")];
+    /// let matcher = TFIDFMatcher::new_with_keys(suppliers, 3, |s| s.0)?;
+    /// let options = FindOptions::new().filter(Filter::allow(matcher.select(|s| s.1 == "GB")));
+    /// let result = matcher.find_with("acme", 2, &options)?;
+    /// assert_eq!(result.matches.len(), 1);
+    /// assert_eq!(result.matches[0].payload.1, "GB");
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    pub fn select(&self, predicate: impl Fn(&T) -> bool) -> DocSet {
+        self.payloads
+            .iter()
+            .enumerate()
+            .filter_map(|(doc, payload)| payload.as_ref().is_some_and(&predicate).then_some(doc))
+            .collect()
+    }
+
+    /// Number of entries that haven't been removed.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.matcher.len()
+    }
+
+    /// Whether every entry has been removed (or there were none to begin with).
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.matcher.is_empty()
+    }
+
+    /// Finds the top-k matches for a single needle; see [`TFIDFMatcher::find`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find<'a>(
+        &'a self,
+        needle: &'a str,
+        top_k: usize,
+    ) -> Result<KeyedNeedle<'a, T>, MatcherError> {
+        self.find_with(needle, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for a single needle according to `options`; see
+    /// [`TFIDFMatcher::find_with`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_with<'a>(
+        &'a self,
+        needle: &'a str,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<KeyedNeedle<'a, T>, MatcherError> {
+        Ok(self.attach(self.matcher.find_with(needle, top_k, options)?))
+    }
+
+    /// Finds the top-k matches for multiple needles; see [`TFIDFMatcher::find_many`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+    ) -> Result<Vec<KeyedNeedle<'a, T>>, MatcherError> {
+        self.find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for multiple needles according to `options`; see
+    /// [`TFIDFMatcher::find_many_with`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many_with<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Vec<KeyedNeedle<'a, T>>, MatcherError> {
+        Ok(self
+            .matcher
+            .find_many_with(needles, top_k, options)?
+            .into_iter()
+            .map(|needle| self.attach(needle))
+            .collect())
+    }
+
+    /// Appends an entry matched by `text` and carrying `payload`, and returns its index; see
+    /// [`TFIDFMatcher::insert`].
+    ///
+    /// # Panics
+    /// Panics if the haystack would exceed `u32::MAX` entries (the inverted index stores document
+    /// indices as `u32`).
+    pub fn insert(&mut self, text: impl Into<String>, payload: T) -> usize {
+        let doc = self.matcher.insert(text);
+        self.payloads.push(Some(payload));
+        doc
+    }
+
+    /// Removes the entry at `haystack_idx` and returns its payload, or `None` if it was out of
+    /// range or already removed; see [`TFIDFMatcher::remove`].
+    pub fn remove(&mut self, haystack_idx: usize) -> Option<T> {
+        if !self.matcher.remove(haystack_idx) {
+            return None;
+        }
+        self.payloads[haystack_idx].take()
+    }
+
+    /// Drops removed entries and rebuilds the index, renumbering the remaining entries; see
+    /// [`TFIDFMatcher::compact`]. Payloads follow their entries.
+    pub fn compact(&mut self) -> Vec<usize> {
+        let kept = self.matcher.compact();
+        self.payloads.retain(Option::is_some);
+        kept
+    }
+
+    /// Pairs each match with its entry's payload.
+    fn attach<'a>(&'a self, needle: Needle<'a>) -> KeyedNeedle<'a, T> {
+        KeyedNeedle {
+            needle: needle.needle,
+            matches: needle
+                .matches
+                .into_iter()
+                .map(|entry| KeyedMatch {
+                    payload: self.payloads[entry.haystack_idx]
+                        .as_ref()
+                        .expect("removed entries are never matched"),
+                    entry,
+                })
+                .collect(),
+        }
+    }
+}
@@ -5,47 +5,151 @@ use std::cmp::Ordering;
 use std::cmp::Ordering::Equal;
 use std::collections::BinaryHeap;
 
+mod analysis;
+mod attributes;
+mod builder;
+mod cluster;
+mod filter;
+mod graph;
+mod incremental;
+mod join;
+mod keyed;
+mod link;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod matrix;
+mod owned;
+#[cfg(feature = "parallel")]
+mod parallel;
+mod persist;
+mod query;
+mod stream;
 mod vectorizer;
-use vectorizer::Vectorizer;
+pub use analysis::{
+    Analyzer, Boundary, CharNgramTokenizer, HybridTokenizer, NormalizationForm, Normalizer,
+    Tokenization, Tokenizer, UnicodeNormalizer, WordTokenizer,
+};
+use attributes::Attributes;
+pub use builder::{MatcherConfig, TFIDFMatcherBuilder};
+pub use cluster::{Canonical, ChooseCanonical, Cluster, ClusterOptions, Clustering, Linkage};
+pub use filter::{DocSet, Filter};
+pub use graph::EdgeFormat;
+pub use keyed::{KeyedMatch, KeyedMatcher, KeyedNeedle};
+pub use link::{Assignment, LinkOptions, Links};
+#[cfg(feature = "mmap")]
+pub use mapped::MappedMatcher;
+pub use owned::{OwnedMatchEntry, OwnedNeedle, SharedMatcher};
+pub use query::{FindOptions, Similarity};
+pub use stream::FindIter;
+pub use vectorizer::{IdfMethod, TfMethod};
+use vectorizer::{Vectorizer, Vocabulary};
 
 #[cfg(test)]
 mod tests;
 
 /// Error type returned by [`TFIDFMatcher`] operations.
-///
-/// The current implementation is infallible, so this type is uninhabited: an `Err` value can never
-/// be constructed. It is kept so the public API stays fallible (`Result`-returning), preserving
-/// source compatibility with call sites that use `?` or `.expect()` and leaving room to introduce
-/// real error conditions later without another breaking change.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum MatcherError {}
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MatcherError {
+    /// An I/O error while reading or writing a persisted matcher.
+    Io(std::io::Error),
+    /// The data does not start with the persisted-matcher magic bytes.
+    NotAMatcherFile,
+    /// The data was written in a format version this build cannot read.
+    UnsupportedVersion {
+        /// Version found in the data.
+        found: u32,
+        /// Version this build reads and writes.
+        supported: u32,
+    },
+    /// The checksum stored with the data does not match its contents.
+    ChecksumMismatch {
+        /// Checksum stored in the data.
+        stored: u32,
+        /// Checksum computed over the data actually read.
+        computed: u32,
+    },
+    /// The data is truncated or structurally invalid.
+    Corrupt(String),
+    /// A [`MatcherConfig`] setting is out of range or inconsistent with another.
+    InvalidConfig(String),
+}
 
 impl std::fmt::Display for MatcherError {
-    fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {}
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+            Self::NotAMatcherFile => f.write_str("not a persisted TF-IDF matcher"),
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "unsupported matcher format version {found} (this build supports {supported})"
+            ),
+            Self::ChecksumMismatch { stored, computed } => write!(
+                f,
+                "matcher checksum mismatch (stored {stored:#010x}, computed {computed:#010x})"
+            ),
+            Self::Corrupt(reason) => write!(f, "corrupt matcher data: {reason}"),
+            Self::InvalidConfig(reason) => write!(f, "invalid matcher configuration: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for MatcherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
     }
 }
 
-impl std::error::Error for MatcherError {}
+impl From<std::io::Error> for MatcherError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Selects how query vectors are weighted, versioned so that a given setting keeps producing the
+/// same scores when the crate's defaults change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ScoringVersion {
+    /// The 0.2 behavior: the IDF is recomputed from every batch of needles passed to
+    /// [`TFIDFMatcher::find`] or [`TFIDFMatcher::find_many`]. A needle's confidence therefore
+    /// depends on which other needles it is batched with, and a single needle passed to `find` has
+    /// every feature weighted equally.
+    V1,
+    /// Needles are weighted with the IDF of the haystack the matcher was built from. Scores are
+    /// deterministic and batch-independent, and both sides of the cosine use the same weights.
+    #[default]
+    V2,
+}
+
 /// A single match result from the corpus.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[must_use]
 pub struct MatchEntry<'a> {
     /// The matched string from the corpus.
     pub haystack: &'a str,
-    /// Similarity score between 0.0 and 1.0.
+    /// Similarity score between 0.0 and 1.0, rounded for presentation per
+    /// [`MatcherConfig::confidence_decimals`].
     pub confidence: f64,
+    /// The full-precision similarity score that `confidence` is rounded from; use it for fine
+    /// thresholds, calibration and telling apart matches whose confidences tie.
+    pub score: f64,
     /// Index of this match in the original corpus.
     pub haystack_idx: usize,
 }
 
 /// Container for query results, holding the original query and its matches.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 #[must_use]
 pub struct Needle<'a> {
     /// The original query string.
     pub needle: &'a str,
-    /// The top-k matches ranked by confidence.
+    /// The top-k matches ranked by score; equal scores are ranked by `haystack_idx`, lowest first.
     pub matches: Vec<MatchEntry<'a>>,
 }
 
@@ -55,6 +159,7 @@ impl Needle<'_> {
             haystack,
             confidence,
             haystack_idx,
+            ..
         } in &self.matches
         {
             println!(
@@ -67,6 +172,8 @@ impl Needle<'_> {
 
 trait Normalize {
     fn normalize(&self) -> Vec<f64>;
+    /// Per-row sum of the weights (the L1 norm, as weights are non-negative).
+    fn weight_sums(&self) -> Vec<f64>;
 }
 impl Normalize for CsMat<f64> {
     #[inline]
@@ -75,6 +182,13 @@ impl Normalize for CsMat<f64> {
             .map(|row| row.data().iter().map(|x| x * x).sum::<f64>().sqrt())
             .collect()
     }
+
+    #[inline]
+    fn weight_sums(&self) -> Vec<f64> {
+        self.outer_iterator()
+            .map(|row| row.data().iter().sum::<f64>())
+            .collect()
+    }
 }
 
 /// A TF-IDF based string matcher for finding approximate matches in a corpus.
@@ -92,16 +206,27 @@ pub struct TFIDFMatcher {
     /// every document in which that feature (n-gram) occurs.
     postings: Vec<Vec<(u32, f64)>>,
     haystack_norm: Vec<f64>,
+    /// Sum of each document's TF-IDF weights, for the set-overlap [`Similarity`] measures.
+    haystack_sum: Vec<f64>,
+    /// Tombstones: `removed[doc]` is set once `doc` is removed. Its postings stay in place (and are
+    /// skipped during scoring) until [`compact`](Self::compact) rebuilds the index.
+    removed: Vec<bool>,
+    /// Tags and numeric fields of the entries, for [`Filter`]s.
+    attributes: Attributes,
     n_docs: usize,
-    ngram_length: usize,
+    config: MatcherConfig,
+    analyzer: Analyzer,
 }
 
 /// Per-thread scratch for the sparse score accumulator, reused across queries so scoring allocates
-/// nothing steady-state. `scores` is indexed by document; `touched` lists the documents given a
-/// nonzero score this query, so only those are read back and reset (never the whole corpus).
+/// nothing steady-state. `scores` and `seen` are indexed by document; `touched` lists the
+/// documents reached this query, so only those are read back and reset (never the whole corpus).
+/// First-touch is tracked by `seen` rather than by a zero score, since zero-IDF features and
+/// underflow leave reached documents at exactly 0.
 #[derive(Default)]
 struct ScoreScratch {
     scores: Vec<f64>,
+    seen: Vec<bool>,
     touched: Vec<u32>,
 }
 
@@ -109,12 +234,6 @@ thread_local! {
     static SCRATCH: RefCell<ScoreScratch> = RefCell::new(ScoreScratch::default());
 }
 
-/// Rounds a similarity score to 2 decimal places.
-#[inline]
-fn round_confidence(sim: f64) -> f64 {
-    (sim * 100.0).round() / 100.0
-}
-
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct Scored {
     sim: f64,
@@ -124,9 +243,14 @@ struct Scored {
 impl Eq for Scored {}
 
 impl Ord for Scored {
+    /// Orders better matches first: higher similarity, then lower index. The heap therefore keeps
+    /// its worst entry on top, and ties are resolved the same way whatever the traversal order.
     fn cmp(&self, other: &Self) -> Ordering {
-        // Reversed comparison for min-heap behavior
-        other.sim.partial_cmp(&self.sim).unwrap_or(Equal)
+        other
+            .sim
+            .partial_cmp(&self.sim)
+            .unwrap_or(Equal)
+            .then(self.idx.cmp(&other.idx))
     }
 }
 
@@ -137,52 +261,17 @@ impl PartialOrd for Scored {
 }
 
 impl TFIDFMatcher {
-    fn text_into_ngrams(text: &str, n: usize) -> String {
-        // Pre-calculate capacity: text length + underscores + 2 boundary chars
-        let word_count = text.split_whitespace().count();
-        let estimated_len = text.len() + word_count.saturating_sub(1) + 2;
-        let mut chars = Vec::with_capacity(estimated_len);
-
-        // Build character sequence: _word1_word2_
-        chars.push('_');
-        let mut first = true;
-        for word in text.split_whitespace() {
-            if !first {
-                chars.push('_');
-            }
-            first = false;
-            chars.extend(word.chars().flat_map(char::to_lowercase));
-        }
-        chars.push('_');
-
-        if chars.len() < n {
-            return String::new();
-        }
-
-        // Estimate result capacity: ~(n+1) chars per n-gram including space
-        let ngram_count = chars.len() - n + 1;
-        let mut result = String::with_capacity(ngram_count * (n + 1));
-
-        for i in 0..=chars.len() - n {
-            // Skip n-grams that cross word boundaries (have _ in middle)
-            if n > 2 && chars[i + 1..i + n - 1].contains(&'_') {
-                continue;
-            }
-            if !result.is_empty() {
-                result.push(' ');
-            }
-            result.extend(&chars[i..i + n]);
-        }
-        result
-    }
-    /// Creates a new TF-IDF matcher from a corpus of strings.
+    /// Creates a new TF-IDF matcher from a corpus of strings, using the default
+    /// [`ScoringVersion`].
     ///
     /// # Arguments
     /// * `haystack` - The corpus of strings to match against.
     /// * `ngram_length` - The length of n-grams to use (e.g., 3 for trigrams).
     ///
+    /// Use [`builder`](Self::builder) to configure the rest of the analysis pipeline.
+    ///
     /// # Errors
-    /// Currently infallible; the `Result` is retained for API stability (see [`MatcherError`]).
+    /// Returns [`MatcherError::InvalidConfig`] if `ngram_length` is 0.
     ///
     /// # Panics
     /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
@@ -194,15 +283,47 @@ impl TFIDFMatcher {
     where
         T: Into<String>,
     {
-        let haystack: Vec<String> = haystack.into_iter().map(Into::into).collect();
-        let processed_haystack: Vec<String> = haystack
-            .iter()
-            .map(|s| Self::text_into_ngrams(s, ngram_length))
-            .collect();
+        Self::with_scoring_version(haystack, ngram_length, ScoringVersion::default())
+    }
+
+    /// Creates a new TF-IDF matcher that weights needles according to `scoring`.
+    ///
+    /// See [`new`](Self::new) for the other arguments.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if `ngram_length` is 0.
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn with_scoring_version<T>(
+        haystack: impl IntoIterator<Item = T>,
+        ngram_length: usize,
+        scoring: ScoringVersion,
+    ) -> Result<Self, MatcherError>
+    where
+        T: Into<String>,
+    {
+        Self::builder()
+            .ngram_length(ngram_length)
+            .scoring_version(scoring)
+            .build(haystack)
+    }
+
+    /// Starts a [`TFIDFMatcherBuilder`] with the default configuration.
+    pub fn builder() -> TFIDFMatcherBuilder {
+        TFIDFMatcherBuilder::new()
+    }
+
+    /// Vectorizes `haystack` and builds the inverted index over it. `config` must be valid.
+    fn build(haystack: Vec<String>, config: MatcherConfig, analyzer: Analyzer) -> Self {
+        let processed_haystack: Vec<Vec<String>> =
+            haystack.iter().map(|s| analyzer.analyze(s)).collect();
 
-        let fitted = Vectorizer::fit(&processed_haystack);
-        let haystack_tfidf = fitted.transform(&processed_haystack);
+        let fitted = Vectorizer::fit(&processed_haystack, config.idf, config.tf);
+        let haystack_tfidf = fitted.transform(&processed_haystack, |token| analyzer.weight(token));
         let haystack_norm = haystack_tfidf.normalize();
+        let haystack_sum = haystack_tfidf.weight_sums();
 
         // Build the inverted index once from the doc-major TF-IDF matrix, then drop the matrix —
         // the postings hold the same nonzeros, transposed, so memory is unchanged.
@@ -214,76 +335,36 @@ impl TFIDFMatcher {
             }
         }
 
-        Ok(Self {
+        Self {
+            removed: vec![false; n_docs],
+            attributes: Attributes::default(),
             haystack,
             fitted,
             postings,
             haystack_norm,
+            haystack_sum,
             n_docs,
-            ngram_length,
-        })
+            config,
+            analyzer,
+        }
     }
 
-    /// Score a query's sparse TF-IDF vector against the corpus via the inverted index and return the
-    /// top-`top_k` `(document, cosine similarity)` matches, highest first. Only documents sharing a
-    /// feature with the query are visited; the per-thread accumulator is reset in place afterwards.
-    fn top_k_matches<'a>(
-        &'a self,
-        needle_v: CsVecView<f64>,
-        q_norm: f64,
-        top_k: usize,
-    ) -> Vec<MatchEntry<'a>> {
-        if top_k == 0 || q_norm == 0.0 {
-            return Vec::new();
-        }
-        SCRATCH.with(|cell| {
-            let ScoreScratch { scores, touched } = &mut *cell.borrow_mut();
-            if scores.len() < self.n_docs {
-                scores.resize(self.n_docs, 0.0);
-            }
-            // Accumulate dot products: for each query feature, add q_weight * d_weight to every
-            // document carrying that feature, recording first-touch so the reset stays sparse.
-            for (feature, &q_weight) in needle_v.iter() {
-                let Some(list) = self.postings.get(feature) else {
-                    continue;
-                };
-                for &(doc, d_weight) in list {
-                    let score = &mut scores[doc as usize];
-                    if *score == 0.0 {
-                        touched.push(doc);
-                    }
-                    *score += q_weight * d_weight;
-                }
-            }
+    /// The configuration this matcher was built with.
+    #[must_use]
+    pub fn config(&self) -> &MatcherConfig {
+        &self.config
+    }
 
-            let mut heap: BinaryHeap<Scored> = BinaryHeap::with_capacity(top_k + 1);
-            for &doc in touched.iter() {
-                let d = doc as usize;
-                let denom = q_norm * self.haystack_norm[d];
-                let sim = if denom == 0.0 { 0.0 } else { scores[d] / denom };
-                scores[d] = 0.0; // reset in place; `touched` is cleared below
-                let entry = Scored { sim, idx: d };
-                if heap.len() < top_k {
-                    heap.push(entry);
-                } else if heap
-                    .peek()
-                    .is_some_and(|min_entry| entry.sim > min_entry.sim)
-                {
-                    heap.pop();
-                    heap.push(entry);
-                }
-            }
-            touched.clear();
+    /// The analysis pipeline haystack entries and needles go through.
+    #[must_use]
+    pub fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
 
-            heap.into_sorted_vec()
-                .into_iter()
-                .map(|scored| MatchEntry {
-                    haystack: &self.haystack[scored.idx],
-                    haystack_idx: scored.idx,
-                    confidence: round_confidence(scored.sim),
-                })
-                .collect()
-        })
+    /// The [`ScoringVersion`] this matcher weights needles with.
+    #[must_use]
+    pub fn scoring_version(&self) -> ScoringVersion {
+        self.config.scoring
     }
 
     /// Finds the top-k matches for a single needle string.
@@ -291,17 +372,81 @@ impl TFIDFMatcher {
     /// Returns a [`Needle`] containing the query and its ranked matches.
     ///
     /// # Errors
-    /// Currently infallible; the `Result` is retained for API stability (see [`MatcherError`]).
+    /// Currently infallible; the `Result` is retained for API stability.
     ///
     /// # Panics
     /// Panics if the TF-IDF transformation returns an empty result (should not happen).
     pub fn find<'a>(&'a self, needle: &'a str, top_k: usize) -> Result<Needle<'a>, MatcherError> {
-        let needle_ngrams = Self::text_into_ngrams(needle, self.ngram_length);
-        let needles_tfidf = self.fitted.transform([needle_ngrams.as_str()]);
-        let needle_v = needles_tfidf.outer_view(0).unwrap();
-        let q_norm = needles_tfidf.normalize()[0];
-        let matches = self.top_k_matches(needle_v, q_norm, top_k);
-        Ok(Needle { needle, matches })
+        self.find_with(needle, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for a single needle string, scored and filtered according to
+    /// `options`. Pass `usize::MAX` as `top_k` to keep every match that passes the threshold.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_with<'a>(
+        &'a self,
+        needle: &'a str,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Needle<'a>, MatcherError> {
+        Ok(self.search(needle, top_k, options))
+    }
+
+    /// Finds every match for a single needle with a confidence of at least `min_confidence`,
+    /// however many there are, highest first.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_above<'a>(
+        &'a self,
+        needle: &'a str,
+        min_confidence: f64,
+    ) -> Result<Needle<'a>, MatcherError> {
+        let options = FindOptions::new().min_confidence(min_confidence);
+        self.find_with(needle, usize::MAX, &options)
+    }
+
+    /// Finds every match with a confidence of at least `min_confidence` for each of multiple
+    /// needles; see [`find_above`](Self::find_above).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many_above<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        min_confidence: f64,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        let options = FindOptions::new().min_confidence(min_confidence);
+        self.find_many_with(needles, usize::MAX, &options)
+    }
+
+    /// Lazily finds the top-k matches for each needle drawn from `needles`, yielding
+    /// `(needle, matches)` pairs in order. Needles are only read as the iterator is advanced, in
+    /// chunks of [`FindIter::chunk_size`], so arbitrarily long sources such as the lines of a file
+    /// are matched in bounded memory.
+    pub fn find_iter<I>(&self, needles: I, top_k: usize) -> FindIter<'_, Self, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.find_iter_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Like [`find_iter`](Self::find_iter), with matches scored and filtered according to
+    /// `options`.
+    pub fn find_iter_with<I>(
+        &self,
+        needles: I,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> FindIter<'_, Self, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        FindIter::new(self, needles.into_iter(), top_k, options.clone())
     }
 
     /// Returns the indices of active TF-IDF features for a needle.
@@ -312,9 +457,7 @@ impl TFIDFMatcher {
     /// Panics if the TF-IDF transformation returns an empty result (should not happen).
     #[must_use]
     pub fn features(&self, needle: &str) -> Vec<usize> {
-        let needle_ngrams = Self::text_into_ngrams(needle, self.ngram_length);
-        self.fitted
-            .transform([needle_ngrams.as_str()])
+        self.vectorize_needles([self.analyzer.analyze(needle)])
             .outer_view(0)
             .expect("Outer view failed")
             .indices()
@@ -324,10 +467,11 @@ impl TFIDFMatcher {
     /// Finds the top-k matches for multiple needle strings.
     ///
     /// More efficient than calling [`find`](Self::find) repeatedly due to
-    /// batched TF-IDF transformation and heap-based top-k selection.
+    /// batched TF-IDF transformation and heap-based top-k selection. Under
+    /// [`ScoringVersion::V2`] every needle scores exactly as it would through `find`.
     ///
     /// # Errors
-    /// Currently infallible; the `Result` is retained for API stability (see [`MatcherError`]).
+    /// Currently infallible; the `Result` is retained for API stability.
     ///
     /// # Panics
     /// Panics if the TF-IDF transformation returns fewer rows than expected.
@@ -335,22 +479,228 @@ impl TFIDFMatcher {
         &'a self,
         needles: impl Into<Vec<&'a str>>,
         top_k: usize,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        self.find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for multiple needle strings, scored and filtered according to
+    /// `options`. Pass `usize::MAX` as `top_k` to keep every match that passes the threshold.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many_with<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
     ) -> Result<Vec<Needle<'a>>, MatcherError> {
         let needles: Vec<&str> = needles.into();
-        let needle_ngrams: Vec<String> = needles
+        Ok(self.search_many(&needles, top_k, options))
+    }
+}
+
+impl Index for TFIDFMatcher {
+    type Vocab = Vectorizer;
+
+    fn vocabulary(&self) -> &Vectorizer {
+        &self.fitted
+    }
+
+    fn config(&self) -> &MatcherConfig {
+        &self.config
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn n_docs(&self) -> usize {
+        self.n_docs
+    }
+
+    fn postings(&self, feature: usize) -> impl Iterator<Item = (u32, f64)> + '_ {
+        self.postings.get(feature).into_iter().flatten().copied()
+    }
+
+    fn norm(&self, doc: usize) -> f64 {
+        self.haystack_norm[doc]
+    }
+
+    fn weight_sum(&self, doc: usize) -> f64 {
+        self.haystack_sum[doc]
+    }
+
+    fn haystack(&self, doc: usize) -> &str {
+        &self.haystack[doc]
+    }
+
+    fn is_removed(&self, doc: usize) -> bool {
+        self.removed[doc]
+    }
+
+    fn attributes(&self) -> &Attributes {
+        &self.attributes
+    }
+}
+
+/// Read access to a built index. Implemented by [`TFIDFMatcher`] and by the memory-mapped
+/// matcher, which share the query path provided here.
+trait Index {
+    type Vocab: Vocabulary;
+
+    fn vocabulary(&self) -> &Self::Vocab;
+    fn config(&self) -> &MatcherConfig;
+    fn analyzer(&self) -> &Analyzer;
+    fn n_docs(&self) -> usize;
+    /// The `(document index, tf-idf weight)` postings of `feature`.
+    fn postings(&self, feature: usize) -> impl Iterator<Item = (u32, f64)> + '_;
+    /// Euclidean norm of a document's TF-IDF vector.
+    fn norm(&self, doc: usize) -> f64;
+    /// Sum of the weights of a document's TF-IDF vector.
+    fn weight_sum(&self, doc: usize) -> f64;
+    fn haystack(&self, doc: usize) -> &str;
+    /// Whether `doc` has been removed and must not be returned.
+    fn is_removed(&self, doc: usize) -> bool;
+    /// The tags and numeric fields [`Filter`]s are resolved against.
+    fn attributes(&self) -> &Attributes;
+
+    /// Vectorizes a batch of analyzed needles, according to the scoring version.
+    fn vectorize_needles<I, D>(&self, needle_ngrams: I) -> CsMat<f64>
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[String]>,
+    {
+        let weight = |token: &str| self.analyzer().weight(token);
+        match self.config().scoring {
+            ScoringVersion::V1 => self.vocabulary().transform_batch_idf(needle_ngrams, weight),
+            ScoringVersion::V2 => self.vocabulary().transform(needle_ngrams, weight),
+        }
+    }
+
+    /// The weight sum of an analyzed needle whose vector is `needle_v`. Features the vocabulary
+    /// lacks are missing from the vector but still count, so set-based similarities penalize
+    /// needle words no entry has.
+    fn needle_sum(&self, needle_ngrams: &[String], needle_v: CsVecView<f64>) -> f64 {
+        let unseen = self
+            .vocabulary()
+            .unseen_weight(needle_ngrams, |token| self.analyzer().weight(token));
+        needle_v.data().iter().sum::<f64>() + unseen
+    }
+
+    /// Score a query's sparse TF-IDF vector against the corpus via the inverted index and return the
+    /// top-`top_k` `(document, similarity)` matches passing the threshold, highest first. `q_norm`
+    /// and `q_sum` are the query's norm and [weight sum](Self::needle_sum). Only documents sharing
+    /// a feature with the query are visited; the per-thread accumulator is reset in place
+    /// afterwards.
+    fn top_k_matches(
+        &self,
+        needle_v: CsVecView<f64>,
+        q_norm: f64,
+        q_sum: f64,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Vec<MatchEntry<'_>> {
+        if top_k == 0 || q_norm == 0.0 {
+            return Vec::new();
+        }
+        let similarity = options.similarity;
+        let filter = options
+            .filter
+            .as_ref()
+            .map(|filter| filter.resolve(self.attributes()));
+        SCRATCH.with(|cell| {
+            let ScoreScratch {
+                scores,
+                seen,
+                touched,
+            } = &mut *cell.borrow_mut();
+            if scores.len() < self.n_docs() {
+                scores.resize(self.n_docs(), 0.0);
+                seen.resize(self.n_docs(), false);
+            }
+            // Accumulate per document: for each query feature, add the contribution of q_weight
+            // and d_weight (their product for cosine) to every document carrying that feature,
+            // recording first-touch so the reset stays sparse.
+            for (feature, &q_weight) in needle_v.iter() {
+                for (doc, d_weight) in self.postings(feature) {
+                    let d = doc as usize;
+                    if !seen[d] {
+                        seen[d] = true;
+                        touched.push(doc);
+                    }
+                    scores[d] += similarity.accumulate(q_weight, d_weight);
+                }
+            }
+
+            let mut heap: BinaryHeap<Scored> =
+                BinaryHeap::with_capacity(top_k.min(touched.len()) + 1);
+            for &doc in touched.iter() {
+                let d = doc as usize;
+                let sim =
+                    similarity.finish(scores[d], q_norm, q_sum, self.norm(d), self.weight_sum(d));
+                // Reset in place; `touched` is cleared below.
+                scores[d] = 0.0;
+                seen[d] = false;
+                if self.is_removed(d)
+                    || filter.as_ref().is_some_and(|filter| !filter.admits(d))
+                    || !options.accepts(sim)
+                {
+                    continue;
+                }
+                let entry = Scored { sim, idx: d };
+                if heap.len() < top_k {
+                    heap.push(entry);
+                } else if heap.peek().is_some_and(|worst| entry < *worst) {
+                    heap.pop();
+                    heap.push(entry);
+                }
+            }
+            touched.clear();
+
+            heap.into_sorted_vec()
+                .into_iter()
+                .map(|scored| MatchEntry {
+                    haystack: self.haystack(scored.idx),
+                    haystack_idx: scored.idx,
+                    confidence: self.config().round_confidence(scored.sim),
+                    score: scored.sim,
+                })
+                .collect()
+        })
+    }
+
+    /// Backs `find`: vectorizes a single needle and returns its top-k matches.
+    fn search<'a>(&'a self, needle: &'a str, top_k: usize, options: &FindOptions) -> Needle<'a> {
+        let needle_ngrams = self.analyzer().analyze(needle);
+        let needles_tfidf = self.vectorize_needles([&needle_ngrams]);
+        let needle_v = needles_tfidf.outer_view(0).unwrap();
+        let q_norm = needles_tfidf.normalize()[0];
+        let q_sum = self.needle_sum(&needle_ngrams, needle_v);
+        let matches = self.top_k_matches(needle_v, q_norm, q_sum, top_k, options);
+        Needle { needle, matches }
+    }
+
+    /// Backs `find_many`: vectorizes the needles as one batch and returns each one's top-k matches.
+    fn search_many<'a>(
+        &'a self,
+        needles: &[&'a str],
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Vec<Needle<'a>> {
+        let needle_ngrams: Vec<Vec<String>> = needles
             .iter()
-            .map(|needle| Self::text_into_ngrams(needle, self.ngram_length))
+            .map(|needle| self.analyzer().analyze(needle))
             .collect();
-        let needles_tfidf = self.fitted.transform(&needle_ngrams);
+        let needles_tfidf = self.vectorize_needles(&needle_ngrams);
         let needles_norm = needles_tfidf.normalize();
 
         let mut results = Vec::with_capacity(needles.len());
         for (i, &needle) in needles.iter().enumerate() {
             let needle_vec: CsVecView<f64> = needles_tfidf.outer_view(i).unwrap();
-            let matches = self.top_k_matches(needle_vec, needles_norm[i], top_k);
+            let q_sum = self.needle_sum(&needle_ngrams[i], needle_vec);
+            let matches = self.top_k_matches(needle_vec, needles_norm[i], q_sum, top_k, options);
             results.push(Needle { needle, matches });
         }
-
-        Ok(results)
+        results
     }
 }
@@ -0,0 +1,368 @@
+//! Record linkage: pairing the records of two lists one-to-one.
+//!
+//! Candidates are the top-k matches of each left record against a matcher built over the right
+//! list. An assignment then keeps at most one pair per record on either side: greedily by score,
+//! or optimally, maximizing the total score of the pairs. The optimal assignment is solved by
+//! shortest augmenting paths over the candidate pairs alone, never a dense cost matrix, so memory
+//! stays linear in the number of candidates.
+
+use crate::{FindOptions, MatcherError, TFIDFMatcher, TFIDFMatcherBuilder};
+use std::collections::BinaryHeap;
+
+/// How candidate pairs are turned into a one-to-one assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Assignment {
+    /// Take pairs from the highest score down, skipping pairs with a record already taken. Fast,
+    /// but a strong pair can block two slightly weaker ones whose total is higher.
+    #[default]
+    Greedy,
+    /// Maximize the total score of the pairs. Solved by shortest augmenting paths on the sparse
+    /// candidate graph (the Hungarian method in its Jonker-Volgenant form); each left record
+    /// costs one search over the pairs near it, so expect it to be slower than greedy on large,
+    /// densely connected lists, but never quadratic in memory.
+    Optimal,
+}
+
+/// Options for [`TFIDFMatcher::link`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[non_exhaustive]
+#[must_use]
+pub struct LinkOptions {
+    /// How the one-to-one assignment is made.
+    pub assignment: Assignment,
+    /// How many candidates are considered per left record.
+    pub top_k: usize,
+}
+
+impl Default for LinkOptions {
+    fn default() -> Self {
+        Self {
+            assignment: Assignment::default(),
+            top_k: 10,
+        }
+    }
+}
+
+impl LinkOptions {
+    /// The default options: greedy assignment over the top 10 candidates of each left record.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how the one-to-one assignment is made.
+    pub fn assignment(mut self, assignment: Assignment) -> Self {
+        self.assignment = assignment;
+        self
+    }
+
+    /// Sets how many candidates are considered per left record.
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.top_k = top_k;
+        self
+    }
+}
+
+/// The result of [`TFIDFMatcher::link`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Links {
+    /// The linked `(left index, haystack_idx, score)` triples, ordered by left index. Every record
+    /// appears in at most one pair.
+    pub pairs: Vec<(usize, usize, f64)>,
+    /// The left indices without a pair, ascending.
+    pub unmatched_left: Vec<usize>,
+    /// The haystack indices without a pair, ascending. Removed entries are not listed.
+    pub unmatched_right: Vec<usize>,
+}
+
+impl TFIDFMatcher {
+    /// Links the records of `left` one-to-one with the haystack entries (the right list): each
+    /// record and each entry appears in at most one pair, scoring at least `threshold`.
+    ///
+    /// ```
+    /// use tf_idf_matcher::{Assignment, LinkOptions, TFIDFMatcher};
+    ///
+    /// let right = TFIDFMatcher::new(["Acme Corp", "Globex Inc", "Initech"], 3)?;
+    /// let options = LinkOptions::new().assignment(Assignment::Optimal);
+    /// let links = right.link(["Globex", "ACME Corp", "Umbrella"], 0.3, &options)?;
+    /// let linked: Vec<_> = links.pairs.iter().map(|&(l, r, _)| (l, r)).collect();
+    /// assert_eq!(linked, [(0, 1), (1, 0)]);
+    /// assert_eq!(links.unmatched_left, [2]);
+    /// assert_eq!(links.unmatched_right, [2]);
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn link<'a>(
+        &self,
+        left: impl Into<Vec<&'a str>>,
+        threshold: f64,
+        options: &LinkOptions,
+    ) -> Result<Links, MatcherError> {
+        let left: Vec<&str> = left.into();
+        let find = FindOptions::new().min_confidence(threshold);
+        let candidates: Vec<(usize, usize, f64)> = self
+            .find_many_with(left.clone(), options.top_k, &find)?
+            .into_iter()
+            .enumerate()
+            .flat_map(|(l, needle)| {
+                needle
+                    .matches
+                    .into_iter()
+                    .map(move |entry| (l, entry.haystack_idx, entry.score))
+            })
+            .collect();
+
+        let mut pairs = match options.assignment {
+            Assignment::Greedy => greedy(candidates, left.len(), self.n_docs),
+            Assignment::Optimal => optimal(&candidates, left.len(), self.n_docs),
+        };
+        pairs.sort_unstable_by_key(|&(l, _, _)| l);
+
+        let mut left_taken = vec![false; left.len()];
+        let mut right_taken = self.removed.clone();
+        for &(l, r, _) in &pairs {
+            left_taken[l] = true;
+            right_taken[r] = true;
+        }
+        let unmatched = |taken: Vec<bool>| {
+            taken
+                .into_iter()
+                .enumerate()
+                .filter_map(|(idx, taken)| (!taken).then_some(idx))
+                .collect()
+        };
+        Ok(Links {
+            pairs,
+            unmatched_left: unmatched(left_taken),
+            unmatched_right: unmatched(right_taken),
+        })
+    }
+}
+
+impl TFIDFMatcherBuilder {
+    /// Builds a matcher over `right` and [links](TFIDFMatcher::link) the records of `left` to it
+    /// one-to-one. Build the matcher over the larger or more stable list, as it is indexed once.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if the configuration is invalid (see
+    /// [`MatcherConfig::validate`](crate::MatcherConfig::validate)).
+    ///
+    /// # Panics
+    /// Panics if `right` contains more than `u32::MAX` records (the inverted index stores
+    /// document indices as `u32`).
+    pub fn link<'a>(
+        &self,
+        left: impl Into<Vec<&'a str>>,
+        right: impl IntoIterator<Item = impl Into<String>>,
+        threshold: f64,
+        options: &LinkOptions,
+    ) -> Result<Links, MatcherError> {
+        self.build(right)?.link(left, threshold, options)
+    }
+}
+
+/// Takes candidates from the highest score down (ties in index order) while both records are
+/// free.
+fn greedy(
+    mut candidates: Vec<(usize, usize, f64)>,
+    n_left: usize,
+    n_right: usize,
+) -> Vec<(usize, usize, f64)> {
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+    let mut left_taken = vec![false; n_left];
+    let mut right_taken = vec![false; n_right];
+    candidates
+        .into_iter()
+        .filter(|&(l, r, _)| {
+            if left_taken[l] || right_taken[r] {
+                return false;
+            }
+            left_taken[l] = true;
+            right_taken[r] = true;
+            true
+        })
+        .collect()
+}
+
+/// The one-to-one subset of `candidates` with the highest total score.
+///
+/// This is the shortest augmenting path method (sparse Jonker-Volgenant) on the candidate graph:
+/// left records are added one at a time, each by a Dijkstra search over reduced costs from the new
+/// record to the nearest free column, whose path then flips the assignment. Every left record also
+/// has a private zero-cost column standing for "unmatched", which makes the assignment optional
+/// without a dense cost matrix. A search stops at the first free column it reaches, so it only
+/// explores the alternating tree around its record, and memory stays linear in the candidates.
+fn optimal(
+    candidates: &[(usize, usize, f64)],
+    n_left: usize,
+    n_right: usize,
+) -> Vec<(usize, usize, f64)> {
+    // Costs are negated scores; a pair scoring 0 can't beat leaving both records unmatched.
+    let mut edges: Vec<Vec<(usize, f64)>> = vec![Vec::new(); n_left];
+    for &(l, r, score) in candidates.iter().filter(|candidate| candidate.2 > 0.0) {
+        edges[l].push((r, -score));
+    }
+    let mut solver = Assigner::new(&edges, n_right);
+    for l in (0..n_left).filter(|&l| !edges[l].is_empty()) {
+        solver.add_row(l);
+    }
+    solver
+        .row_col
+        .iter()
+        .enumerate()
+        .filter_map(|(l, &col)| {
+            let r = col.filter(|&col| col < n_right)?;
+            let cost = edges[l].iter().find(|edge| edge.0 == r)?.1;
+            Some((l, r, -cost))
+        })
+        .collect()
+}
+
+/// The state of [`optimal`]: a minimum-cost assignment of the rows added so far, with dual
+/// potentials keeping every reduced cost `cost - row_pot - col_pot` non-negative and those of
+/// assigned pairs at 0. Columns `0..n_right` are the right records; column `n_right + l` is the
+/// "unmatched" column of left record `l`.
+struct Assigner<'e> {
+    /// The candidate `(column, cost)` edges of each row.
+    edges: &'e [Vec<(usize, f64)>],
+    n_right: usize,
+    row_col: Vec<Option<usize>>,
+    col_row: Vec<Option<usize>>,
+    row_pot: Vec<f64>,
+    col_pot: Vec<f64>,
+    /// Per-search scratch, reset sparsely through `reached`.
+    dist: Vec<f64>,
+    via: Vec<usize>,
+    done: Vec<bool>,
+    reached: Vec<usize>,
+}
+
+/// A column in the search frontier; the heap pops the nearest first, ties to the lower column.
+#[derive(PartialEq)]
+struct Frontier {
+    dist: f64,
+    col: usize,
+}
+
+impl Eq for Frontier {}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .dist
+            .total_cmp(&self.dist)
+            .then(other.col.cmp(&self.col))
+    }
+}
+
+impl<'e> Assigner<'e> {
+    fn new(edges: &'e [Vec<(usize, f64)>], n_right: usize) -> Self {
+        let n_left = edges.len();
+        let n_cols = n_right + n_left;
+        Self {
+            edges,
+            n_right,
+            row_col: vec![None; n_left],
+            col_row: vec![None; n_cols],
+            row_pot: vec![0.0; n_left],
+            col_pot: vec![0.0; n_cols],
+            dist: vec![f64::INFINITY; n_cols],
+            via: vec![0; n_cols],
+            done: vec![false; n_cols],
+            reached: Vec::new(),
+        }
+    }
+
+    /// Adds `row` and re-optimizes the assignment.
+    fn add_row(&mut self, row: usize) {
+        // Start feasible: no reduced cost of the new row below 0. Its "unmatched" column has
+        // never been reached, so its potential is still 0.
+        self.row_pot[row] = self.edges[row]
+            .iter()
+            .map(|&(col, cost)| cost - self.col_pot[col])
+            .fold(0.0, f64::min);
+
+        let mut heap = BinaryHeap::new();
+        // Rows settled by the search, with their distances.
+        let mut tree = vec![(row, 0.0)];
+        self.relax(row, 0.0, &mut heap);
+        let (end, length) = loop {
+            let Frontier { dist, col } = heap.pop().expect("the row's own column is always free");
+            if self.done[col] || dist > self.dist[col] {
+                continue;
+            }
+            self.done[col] = true;
+            let Some(next) = self.col_row[col] else {
+                break (col, dist);
+            };
+            // Assigned pairs have reduced cost 0: the row is as far as its column.
+            tree.push((next, dist));
+            self.relax(next, dist, &mut heap);
+        };
+
+        // Shift the potentials of everything settled closer than the free column, keeping reduced
+        // costs non-negative and making the path found tight.
+        for &(settled, dist) in &tree {
+            self.row_pot[settled] += length - dist;
+        }
+        for &col in &self.reached {
+            if self.done[col] {
+                self.col_pot[col] -= length - self.dist[col];
+            }
+        }
+
+        // Flip the path back from the free column to the new row.
+        let mut col = end;
+        loop {
+            let from = self.via[col];
+            let previous = self.row_col[from];
+            self.row_col[from] = Some(col);
+            self.col_row[col] = Some(from);
+            if from == row {
+                break;
+            }
+            col = previous.expect("rows on the path are assigned");
+        }
+
+        for col in self.reached.drain(..) {
+            self.dist[col] = f64::INFINITY;
+            self.done[col] = false;
+        }
+    }
+
+    /// Offers the columns of `row`, settled at distance `dist`, to the search frontier: its
+    /// candidates and its "unmatched" column.
+    fn relax(&mut self, row: usize, dist: f64, heap: &mut BinaryHeap<Frontier>) {
+        let unmatched = (self.n_right + row, 0.0);
+        for (col, cost) in self.edges[row].iter().copied().chain([unmatched]) {
+            if self.done[col] {
+                continue;
+            }
+            // Clamped against rounding; exact arithmetic never goes below 0.
+            let reduced = (cost - self.row_pot[row] - self.col_pot[col]).max(0.0);
+            let candidate = dist + reduced;
+            if candidate < self.dist[col] {
+                if self.dist[col].is_infinite() {
+                    self.reached.push(col);
+                }
+                self.dist[col] = candidate;
+                self.via[col] = row;
+                heap.push(Frontier {
+                    dist: candidate,
+                    col,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! A read-only matcher that queries a persisted index in place through a memory map.
+
+use crate::attributes::Attributes;
+use crate::persist::Layout;
+use crate::vectorizer::Vocabulary;
+use crate::vectorizer::{IdfMethod, TfMethod};
+use crate::{
+    Analyzer, FindIter, FindOptions, Index, MatcherConfig, MatcherError, Needle, ScoringVersion,
+};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// A read-only matcher over a file written by [`TFIDFMatcher::save`](crate::TFIDFMatcher::save),
+/// queried directly through a memory map.
+///
+/// Nothing is deserialized up front: the haystack, vocabulary, postings and norms are read from the
+/// mapped pages as queries touch them, and the entries' tags and numeric fields are decoded by the
+/// first query with a [`Filter`](crate::Filter). Opening is constant-time regardless of corpus
+/// size, and processes mapping the same file share one copy of it in the page cache.
+///
+/// Results are identical to those of the [`TFIDFMatcher`](crate::TFIDFMatcher) that was saved.
+#[derive(Debug)]
+pub struct MappedMatcher {
+    map: Mmap,
+    layout: Layout,
+    analyzer: Analyzer,
+    attributes: OnceLock<Attributes>,
+}
+
+impl MappedMatcher {
+    /// Maps the index file at `path`.
+    ///
+    /// Only the header and section bounds are checked, so this doesn't read the file's contents.
+    /// Call [`verify`](Self::verify) to check the checksum and structure before trusting a file
+    /// that may be damaged.
+    ///
+    /// # Safety
+    /// The file must not be modified or truncated while the matcher is alive; doing so is undefined
+    /// behavior (see [`memmap2::Mmap::map`]).
+    ///
+    /// # Errors
+    /// * [`MatcherError::Io`] if the file can't be opened or mapped;
+    /// * [`MatcherError::NotAMatcherFile`], [`MatcherError::UnsupportedVersion`] or
+    ///   [`MatcherError::Corrupt`] if the header or section bounds are invalid;
+    /// * [`MatcherError::InvalidConfig`] if the index was built with a custom normalizer or
+    ///   tokenizer, which must be supplied through [`open_with`](Self::open_with).
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, MatcherError> {
+        // SAFETY: upheld by the caller, per this function's contract.
+        unsafe { Self::map(path.as_ref(), None) }
+    }
+
+    /// Maps the index file at `path`, analyzing needles with `analyzer`; see
+    /// [`TFIDFMatcher::load_with`](crate::TFIDFMatcher::load_with).
+    ///
+    /// # Safety
+    /// As for [`open`](Self::open).
+    ///
+    /// # Errors
+    /// As for [`open`](Self::open), except that custom analyzers are accepted.
+    pub unsafe fn open_with(
+        path: impl AsRef<Path>,
+        analyzer: Analyzer,
+    ) -> Result<Self, MatcherError> {
+        // SAFETY: upheld by the caller, per this function's contract.
+        unsafe { Self::map(path.as_ref(), Some(analyzer)) }
+    }
+
+    /// # Safety
+    /// As for [`open`](Self::open).
+    unsafe fn map(path: &Path, analyzer: Option<Analyzer>) -> Result<Self, MatcherError> {
+        let file = File::open(path)?;
+        // SAFETY: upheld by the caller, per this function's contract.
+        let map = unsafe { Mmap::map(&file)? };
+        let layout = Layout::parse(&map)?;
+        let analyzer = layout.analyzer(analyzer)?;
+        Ok(Self {
+            map,
+            layout,
+            analyzer,
+            attributes: OnceLock::new(),
+        })
+    }
+
+    /// Checks the file's checksum and the consistency of every section.
+    ///
+    /// Queries against a file that fails this check may panic (but are otherwise memory-safe).
+    ///
+    /// # Errors
+    /// [`MatcherError::ChecksumMismatch`] or [`MatcherError::Corrupt`].
+    pub fn verify(&self) -> Result<(), MatcherError> {
+        Layout::verify_checksum(&self.map)?;
+        self.layout.validate(&self.map)
+    }
+
+    /// Number of haystack entries, including removed entries that were saved before being
+    /// compacted away.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.layout.n_docs
+    }
+
+    /// Whether the haystack is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.layout.n_docs == 0
+    }
+
+    /// The [`ScoringVersion`] the saved matcher weights needles with.
+    #[must_use]
+    pub fn scoring_version(&self) -> ScoringVersion {
+        self.layout.config.scoring
+    }
+
+    /// The configuration the saved matcher was built with.
+    #[must_use]
+    pub fn config(&self) -> &MatcherConfig {
+        &self.layout.config
+    }
+
+    /// Finds the top-k matches for a single needle string; see
+    /// [`TFIDFMatcher::find`](crate::TFIDFMatcher::find).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find<'a>(&'a self, needle: &'a str, top_k: usize) -> Result<Needle<'a>, MatcherError> {
+        self.find_with(needle, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for a single needle string, scored according to `options`; see
+    /// [`TFIDFMatcher::find_with`](crate::TFIDFMatcher::find_with).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find_with<'a>(
+        &'a self,
+        needle: &'a str,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Needle<'a>, MatcherError> {
+        Ok(self.search(needle, top_k, options))
+    }
+
+    /// Finds every match for a single needle with a confidence of at least `min_confidence`; see
+    /// [`TFIDFMatcher::find_above`](crate::TFIDFMatcher::find_above).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find_above<'a>(
+        &'a self,
+        needle: &'a str,
+        min_confidence: f64,
+    ) -> Result<Needle<'a>, MatcherError> {
+        let options = FindOptions::new().min_confidence(min_confidence);
+        self.find_with(needle, usize::MAX, &options)
+    }
+
+    /// Finds every match with a confidence of at least `min_confidence` for each of multiple
+    /// needles; see [`TFIDFMatcher::find_many_above`](crate::TFIDFMatcher::find_many_above).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find_many_above<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        min_confidence: f64,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        let options = FindOptions::new().min_confidence(min_confidence);
+        self.find_many_with(needles, usize::MAX, &options)
+    }
+
+    /// Lazily finds the top-k matches for each needle drawn from `needles`; see
+    /// [`TFIDFMatcher::find_iter`](crate::TFIDFMatcher::find_iter).
+    ///
+    /// The iterator may panic if the file fails [`verify`](Self::verify).
+    pub fn find_iter<I>(&self, needles: I, top_k: usize) -> FindIter<'_, Self, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        self.find_iter_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Like [`find_iter`](Self::find_iter), with matches scored and filtered according to
+    /// `options`.
+    pub fn find_iter_with<I>(
+        &self,
+        needles: I,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> FindIter<'_, Self, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        FindIter::new(self, needles.into_iter(), top_k, options.clone())
+    }
+
+    /// Finds the top-k matches for multiple needle strings; see
+    /// [`TFIDFMatcher::find_many`](crate::TFIDFMatcher::find_many).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find_many<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        self.find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for multiple needle strings, scored according to `options`; see
+    /// [`TFIDFMatcher::find_many_with`](crate::TFIDFMatcher::find_many_with).
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn find_many_with<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(self.search_many(&needles, top_k, options))
+    }
+}
+
+impl Vocabulary for MappedMatcher {
+    fn feature(&self, token: &str) -> Option<usize> {
+        self.layout.feature(&self.map, token)
+    }
+
+    fn n_features(&self) -> usize {
+        self.layout.n_features
+    }
+
+    fn fitted_docs(&self) -> usize {
+        self.layout.fitted_docs
+    }
+
+    fn doc_freq(&self, feature: usize) -> usize {
+        self.layout.doc_freq(&self.map, feature)
+    }
+
+    fn total_len(&self) -> usize {
+        self.layout.total_len
+    }
+
+    fn idf_method(&self) -> IdfMethod {
+        self.layout.config.idf
+    }
+
+    fn tf_method(&self) -> TfMethod {
+        self.layout.config.tf
+    }
+}
+
+impl Index for MappedMatcher {
+    type Vocab = Self;
+
+    fn vocabulary(&self) -> &Self {
+        self
+    }
+
+    fn config(&self) -> &MatcherConfig {
+        &self.layout.config
+    }
+
+    fn analyzer(&self) -> &Analyzer {
+        &self.analyzer
+    }
+
+    fn n_docs(&self) -> usize {
+        self.layout.n_docs
+    }
+
+    fn postings(&self, feature: usize) -> impl Iterator<Item = (u32, f64)> + '_ {
+        self.layout.postings(&self.map, feature)
+    }
+
+    fn norm(&self, doc: usize) -> f64 {
+        self.layout.norm(&self.map, doc)
+    }
+
+    fn weight_sum(&self, doc: usize) -> f64 {
+        self.layout.weight_sum(&self.map, doc)
+    }
+
+    fn haystack(&self, doc: usize) -> &str {
+        self.layout.haystack(&self.map, doc)
+    }
+
+    fn is_removed(&self, doc: usize) -> bool {
+        self.layout.is_removed(&self.map, doc)
+    }
+
+    fn attributes(&self) -> &Attributes {
+        self.attributes
+            .get_or_init(|| self.layout.attributes(&self.map))
+    }
+}
@@ -0,0 +1,136 @@
+//! Top-k similarity results as a sparse matrix, for graph analysis outside the crate.
+//!
+//! Row `i` holds the scores of needle `i` against its up to `top_k` best haystack entries above
+//! the threshold, in the same CSR layout `sparse_dot_topn` produces. The matrix is assembled from
+//! the inverted-index search directly, so it never holds more than `top_k` entries per row.
+
+use crate::{FindOptions, Index, MatcherError, TFIDFMatcher};
+use sprs::CsMat;
+use std::io::{BufWriter, Write};
+
+/// The `needles × haystack` matrix of each needle's up to `top_k` matches scoring at least
+/// `threshold`, with the full-precision scores as values and columns sorted within each row.
+pub(crate) fn similarity_matrix<I: Index>(
+    index: &I,
+    needles: &[&str],
+    top_k: usize,
+    threshold: f64,
+) -> CsMat<f64> {
+    let options = FindOptions::new().min_confidence(threshold);
+    let mut indptr = Vec::with_capacity(needles.len() + 1);
+    indptr.push(0);
+    let (mut indices, mut data) = (Vec::new(), Vec::new());
+    for needle in index.search_many(needles, top_k, &options) {
+        let mut row: Vec<(usize, f64)> = needle
+            .matches
+            .iter()
+            .map(|entry| (entry.haystack_idx, entry.score))
+            .collect();
+        row.sort_unstable_by_key(|&(doc, _)| doc);
+        for (doc, score) in row {
+            indices.push(doc);
+            data.push(score);
+        }
+        indptr.push(indices.len());
+    }
+    CsMat::new((needles.len(), index.n_docs()), indptr, indices, data)
+}
+
+/// Writes `matrix` to `writer` in the Matrix Market coordinate format.
+pub(crate) fn write_matrix_market<W: Write>(
+    matrix: &CsMat<f64>,
+    writer: W,
+) -> Result<(), MatcherError> {
+    let mut writer = BufWriter::new(writer);
+    sprs::io::write_matrix_market_to_bufwrite(&mut writer, matrix)?;
+    writer.flush()?;
+    Ok(())
+}
+
+impl TFIDFMatcher {
+    /// Computes the sparse `needles × haystack` similarity matrix: row `i` holds the scores of
+    /// needle `i` against its up to `top_k` best matches scoring at least `threshold`, at the
+    /// columns of their `haystack_idx`. Values are the full-precision
+    /// [`score`](crate::MatchEntry::score)s; the matrix has one column per entry ever indexed,
+    /// and removed entries' columns stay empty.
+    ///
+    /// Pass the haystack itself as `needles` for its top-k similarity graph.
+    ///
+    /// ```
+    /// use tf_idf_matcher::TFIDFMatcher;
+    ///
+    /// let matcher = TFIDFMatcher::new(["Acme Corp", "Globex", "Initech"], 3)?;
+    /// let matrix = matcher.similarity_matrix(["ACME Corp.", "Globex Inc", "Umbrella"], 2, 0.5)?;
+    /// assert_eq!(matrix.shape(), (3, 3));
+    /// assert!(matrix.get(0, 0).is_some() && matrix.get(1, 1).is_some());
+    /// assert_eq!(matrix.outer_view(2).unwrap().nnz(), 0);
+    /// # Ok::<(), tf_idf_matcher::MatcherError>(())
+    /// ```
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn similarity_matrix<'a>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        threshold: f64,
+    ) -> Result<CsMat<f64>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(similarity_matrix(self, &needles, top_k, threshold))
+    }
+
+    /// Computes the [`similarity_matrix`](Self::similarity_matrix) and writes it to `writer` in
+    /// the Matrix Market coordinate format, readable by `scipy.io.mmread` among others. An
+    /// already computed matrix can be written with [`sprs::io::write_matrix_market`].
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if writing fails.
+    pub fn save_similarity_matrix<'a, W: Write>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        threshold: f64,
+        writer: W,
+    ) -> Result<(), MatcherError> {
+        write_matrix_market(&self.similarity_matrix(needles, top_k, threshold)?, writer)
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl crate::MappedMatcher {
+    /// Computes the sparse `needles × haystack` similarity matrix; see
+    /// [`TFIDFMatcher::similarity_matrix`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn similarity_matrix<'a>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        threshold: f64,
+    ) -> Result<CsMat<f64>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(similarity_matrix(self, &needles, top_k, threshold))
+    }
+
+    /// Computes the similarity matrix and writes it to `writer` in the Matrix Market coordinate
+    /// format; see [`TFIDFMatcher::save_similarity_matrix`].
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if writing fails.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn save_similarity_matrix<'a, W: Write>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        threshold: f64,
+        writer: W,
+    ) -> Result<(), MatcherError> {
+        write_matrix_market(&self.similarity_matrix(needles, top_k, threshold)?, writer)
+    }
+}
@@ -0,0 +1,185 @@
+//! Results that own their strings, and a shareable matcher handle returning them.
+//!
+//! [`Needle`] and [`MatchEntry`] borrow from the matcher and the query, which ties them to both.
+//! Their owned counterparts can be returned from `spawn_blocking`, sent over channels or stored
+//! after the matcher is gone.
+
+use crate::{FindOptions, MatchEntry, MatcherError, Needle, TFIDFMatcher};
+use std::sync::Arc;
+
+/// An owned [`MatchEntry`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct OwnedMatchEntry {
+    /// The matched string from the corpus.
+    pub haystack: String,
+    /// Similarity score between 0.0 and 1.0, rounded for presentation.
+    pub confidence: f64,
+    /// The full-precision similarity score.
+    pub score: f64,
+    /// Index of this match in the original corpus.
+    pub haystack_idx: usize,
+}
+
+/// An owned [`Needle`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[must_use]
+pub struct OwnedNeedle {
+    /// The original query string.
+    pub needle: String,
+    /// The top-k matches ranked by score.
+    pub matches: Vec<OwnedMatchEntry>,
+}
+
+impl MatchEntry<'_> {
+    /// Copies the matched string so the entry no longer borrows from the matcher.
+    pub fn into_owned(self) -> OwnedMatchEntry {
+        OwnedMatchEntry {
+            haystack: self.haystack.to_owned(),
+            confidence: self.confidence,
+            score: self.score,
+            haystack_idx: self.haystack_idx,
+        }
+    }
+}
+
+impl Needle<'_> {
+    /// Copies the needle and matched strings so the result no longer borrows from the matcher or
+    /// the query.
+    pub fn into_owned(self) -> OwnedNeedle {
+        OwnedNeedle {
+            needle: self.needle.to_owned(),
+            matches: self
+                .matches
+                .into_iter()
+                .map(MatchEntry::into_owned)
+                .collect(),
+        }
+    }
+}
+
+impl From<MatchEntry<'_>> for OwnedMatchEntry {
+    fn from(entry: MatchEntry<'_>) -> Self {
+        entry.into_owned()
+    }
+}
+
+impl From<Needle<'_>> for OwnedNeedle {
+    fn from(needle: Needle<'_>) -> Self {
+        needle.into_owned()
+    }
+}
+
+/// A cheaply cloneable, `'static` handle to a [`TFIDFMatcher`] whose queries return owned
+/// results.
+///
+/// Clones share one matcher through an [`Arc`], so a handle can be moved into threads and async
+/// tasks and its results can outlive the call that produced them.
+///
+/// ```
+/// use tf_idf_matcher::{SharedMatcher, TFIDFMatcher};
+///
+/// let shared = SharedMatcher::from(TFIDFMatcher::new(["Apple Inc", "Microsoft"], 3)?);
+/// let worker = shared.clone();
+/// let result = std::thread::spawn(move || worker.find("apple", 1)).join().unwrap()?;
+/// assert_eq!(result.matches[0].haystack, "Apple Inc");
+/// # Ok::<(), tf_idf_matcher::MatcherError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct SharedMatcher {
+    inner: Arc<TFIDFMatcher>,
+}
+
+impl SharedMatcher {
+    /// Wraps a matcher for sharing.
+    #[must_use]
+    pub fn new(matcher: TFIDFMatcher) -> Self {
+        Self::from(Arc::new(matcher))
+    }
+
+    /// The shared matcher, for queries returning borrowed results.
+    #[must_use]
+    pub fn matcher(&self) -> &TFIDFMatcher {
+        &self.inner
+    }
+
+    /// The shared matcher's [`Arc`].
+    #[must_use]
+    pub fn as_arc(&self) -> &Arc<TFIDFMatcher> {
+        &self.inner
+    }
+
+    /// Finds the top-k matches for a single needle; see [`TFIDFMatcher::find`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find(&self, needle: &str, top_k: usize) -> Result<OwnedNeedle, MatcherError> {
+        self.find_with(needle, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for a single needle according to `options`; see
+    /// [`TFIDFMatcher::find_with`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_with(
+        &self,
+        needle: &str,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<OwnedNeedle, MatcherError> {
+        Ok(self.inner.find_with(needle, top_k, options)?.into_owned())
+    }
+
+    /// Finds the top-k matches for multiple needles; see [`TFIDFMatcher::find_many`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many<'a>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+    ) -> Result<Vec<OwnedNeedle>, MatcherError> {
+        self.find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Finds the top-k matches for multiple needles according to `options`; see
+    /// [`TFIDFMatcher::find_many_with`].
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn find_many_with<'a>(
+        &self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Vec<OwnedNeedle>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(self
+            .inner
+            .find_many_with(needles, top_k, options)?
+            .into_iter()
+            .map(Needle::into_owned)
+            .collect())
+    }
+}
+
+impl From<TFIDFMatcher> for SharedMatcher {
+    fn from(matcher: TFIDFMatcher) -> Self {
+        Self::new(matcher)
+    }
+}
+
+impl From<Arc<TFIDFMatcher>> for SharedMatcher {
+    fn from(inner: Arc<TFIDFMatcher>) -> Self {
+        Self { inner }
+    }
+}
+
+impl AsRef<TFIDFMatcher> for SharedMatcher {
+    fn as_ref(&self) -> &TFIDFMatcher {
+        &self.inner
+    }
+}
@@ -0,0 +1,275 @@
+//! Rayon-backed batch queries and index construction, enabled by the `parallel` feature.
+//!
+//! The parallel methods return exactly what their sequential counterparts do. Under
+//! [`ScoringVersion::V2`] each needle is analyzed, vectorized and scored inside its own task, so no
+//! stage runs on one thread and the batch's vectors are never held at once. Under
+//! [`ScoringVersion::V1`], whose IDF depends on the whole batch, needles are vectorized as one
+//! batch first, as [`TFIDFMatcher::find_many`] does, and then scored across the pool. Each worker
+//! thread uses its own score accumulator, and output keeps the order of the input.
+//!
+//! Index construction is parallel at every stage — analysis, vocabulary fitting in shards that
+//! are merged in corpus order, TF-IDF weighting, and the postings fill — and yields a matcher
+//! identical to the sequential build, down to the bytes it saves.
+
+use crate::attributes::Attributes;
+use crate::vectorizer::Vectorizer;
+use crate::{
+    Analyzer, FindOptions, Index, MatcherConfig, MatcherError, Needle, Normalize, ScoringVersion,
+    TFIDFMatcher, TFIDFMatcherBuilder,
+};
+use rayon::prelude::*;
+use sprs::CsMat;
+use std::ops::Range;
+
+/// Parallel counterpart of [`Index::search_many`].
+fn par_search_many<'a, I>(
+    index: &'a I,
+    needles: &[&'a str],
+    top_k: usize,
+    options: &FindOptions,
+) -> Vec<Needle<'a>>
+where
+    I: Index + Sync,
+{
+    if index.config().scoring == ScoringVersion::V2 {
+        return needles
+            .par_iter()
+            .map(|&needle| index.search(needle, top_k, options))
+            .collect();
+    }
+    let needle_ngrams: Vec<Vec<String>> = needles
+        .par_iter()
+        .map(|needle| index.analyzer().analyze(needle))
+        .collect();
+    let needles_tfidf = index.vectorize_needles(&needle_ngrams);
+    let needles_norm = needles_tfidf.normalize();
+
+    needles
+        .par_iter()
+        .enumerate()
+        .map(|(i, &needle)| {
+            let needle_vec = needles_tfidf.outer_view(i).unwrap();
+            let q_sum = index.needle_sum(&needle_ngrams[i], needle_vec);
+            let matches = index.top_k_matches(needle_vec, needles_norm[i], q_sum, top_k, options);
+            Needle { needle, matches }
+        })
+        .collect()
+}
+
+/// Parallel counterpart of [`TFIDFMatcher::build`].
+fn par_build(haystack: Vec<String>, config: MatcherConfig, analyzer: Analyzer) -> TFIDFMatcher {
+    let processed_haystack: Vec<Vec<String>> =
+        haystack.par_iter().map(|s| analyzer.analyze(s)).collect();
+
+    let fitted = Vectorizer::par_fit(&processed_haystack, config.idf, config.tf);
+    let haystack_tfidf = fitted.par_transform(&processed_haystack, |token| analyzer.weight(token));
+    drop(processed_haystack);
+    let (haystack_norm, haystack_sum) = (0..haystack_tfidf.rows())
+        .into_par_iter()
+        .map(|doc| {
+            let row = haystack_tfidf.outer_view(doc).unwrap();
+            let data = row.data();
+            (
+                data.iter().map(|x| x * x).sum::<f64>().sqrt(),
+                data.iter().sum::<f64>(),
+            )
+        })
+        .unzip();
+    let postings = par_postings(&haystack_tfidf);
+
+    let n_docs = haystack_tfidf.rows();
+    TFIDFMatcher {
+        removed: vec![false; n_docs],
+        attributes: Attributes::default(),
+        haystack,
+        fitted,
+        postings,
+        haystack_norm,
+        haystack_sum,
+        n_docs,
+        config,
+        analyzer,
+    }
+}
+
+/// Transposes a doc-major TF-IDF matrix into postings, visiting each nonzero twice: once to size
+/// the posting lists and once to fill them in place.
+///
+/// The documents are split into one contiguous shard per thread. Each shard first counts its
+/// postings per feature; every posting list is then allocated at its final length and cut into one
+/// slice per shard, in shard order, and each shard scatters its postings into its own slices. Lists
+/// thus come out in ascending document order, as the sequential fill leaves them, and beyond the
+/// matrix and the postings themselves only the per-shard counts and slices are held.
+fn par_postings(matrix: &CsMat<f64>) -> Vec<Vec<(u32, f64)>> {
+    assert!(u32::try_from(matrix.rows()).is_ok(), "corpus exceeds u32");
+    let (n_docs, n_features) = (matrix.rows(), matrix.cols());
+    let (indptr, indices, data) = (matrix.indptr(), matrix.indices(), matrix.data());
+    let shard_len = n_docs.div_ceil(rayon::current_num_threads()).max(1);
+    let shards: Vec<Range<usize>> = (0..n_docs)
+        .step_by(shard_len)
+        .map(|lo| lo..(lo + shard_len).min(n_docs))
+        .collect();
+
+    let counts: Vec<Vec<usize>> = shards
+        .par_iter()
+        .map(|docs| {
+            let mut counts = vec![0; n_features];
+            for &feature in &indices[indptr.outer_inds_slice(docs.start, docs.end)] {
+                counts[feature] += 1;
+            }
+            counts
+        })
+        .collect();
+    let mut postings: Vec<Vec<(u32, f64)>> = (0..n_features)
+        .into_par_iter()
+        .map(|feature| vec![(0, 0.0); counts.iter().map(|counts| counts[feature]).sum()])
+        .collect();
+
+    // `slots[shard][feature]` is the part of the feature's list the shard has yet to fill.
+    let mut slots: Vec<Vec<&mut [(u32, f64)]>> = shards
+        .iter()
+        .map(|_| Vec::with_capacity(n_features))
+        .collect();
+    for (feature, list) in postings.iter_mut().enumerate() {
+        let mut rest = list.as_mut_slice();
+        for (shard, counts) in slots.iter_mut().zip(&counts) {
+            let (slot, tail) = rest.split_at_mut(counts[feature]);
+            shard.push(slot);
+            rest = tail;
+        }
+    }
+    drop(counts);
+
+    slots
+        .into_par_iter()
+        .zip(shards)
+        .for_each(|(mut slots, docs)| {
+            for doc in docs {
+                #[allow(clippy::cast_possible_truncation)] // checked against u32 above
+                let doc_id = doc as u32;
+                let row = indptr.outer_inds_sz(doc);
+                for (&feature, &weight) in indices[row.clone()].iter().zip(&data[row]) {
+                    let (posting, rest) = std::mem::take(&mut slots[feature])
+                        .split_first_mut()
+                        .expect("posting counted for the shard");
+                    *posting = (doc_id, weight);
+                    slots[feature] = rest;
+                }
+            }
+        });
+    postings
+}
+
+impl TFIDFMatcherBuilder {
+    /// Like [`build`](Self::build), but builds the index across the rayon thread pool. The
+    /// resulting matcher is identical to the one `build` returns.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if the configuration is invalid (see
+    /// [`MatcherConfig::validate`]).
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn par_build<T>(
+        &self,
+        haystack: impl IntoIterator<Item = T>,
+    ) -> Result<TFIDFMatcher, MatcherError>
+    where
+        T: Into<String>,
+    {
+        self.config().validate()?;
+        Ok(par_build(
+            haystack.into_iter().map(Into::into).collect(),
+            self.config().clone(),
+            self.analyzer(),
+        ))
+    }
+}
+
+impl TFIDFMatcher {
+    /// Like [`new`](Self::new), but builds the index across the rayon thread pool.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::InvalidConfig`] if `ngram_length` is 0.
+    ///
+    /// # Panics
+    /// Panics if the corpus contains more than `u32::MAX` documents (the inverted index stores
+    /// document indices as `u32`).
+    pub fn par_new<T>(
+        haystack: impl IntoIterator<Item = T>,
+        ngram_length: usize,
+    ) -> Result<Self, MatcherError>
+    where
+        T: Into<String>,
+    {
+        Self::builder()
+            .ngram_length(ngram_length)
+            .par_build(haystack)
+    }
+
+    /// Like [`find_many`](Self::find_many), but scores the needles across the rayon thread pool.
+    /// Results are in the order of `needles`.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn par_find_many<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        self.par_find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Like [`find_many_with`](Self::find_many_with), but scores the needles across the rayon
+    /// thread pool. Results are in the order of `needles`.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    pub fn par_find_many_with<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(par_search_many(self, &needles, top_k, options))
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl crate::MappedMatcher {
+    /// Like [`find_many`](Self::find_many), but scores the needles across the rayon thread pool.
+    /// Results are in the order of `needles`.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn par_find_many<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        self.par_find_many_with(needles, top_k, &FindOptions::default())
+    }
+
+    /// Like [`find_many_with`](Self::find_many_with), but scores the needles across the rayon
+    /// thread pool. Results are in the order of `needles`.
+    ///
+    /// # Errors
+    /// Currently infallible; the `Result` is retained for API stability.
+    ///
+    /// # Panics
+    /// May panic if the file fails [`verify`](Self::verify).
+    pub fn par_find_many_with<'a>(
+        &'a self,
+        needles: impl Into<Vec<&'a str>>,
+        top_k: usize,
+        options: &FindOptions,
+    ) -> Result<Vec<Needle<'a>>, MatcherError> {
+        let needles: Vec<&str> = needles.into();
+        Ok(par_search_many(self, &needles, top_k, options))
+    }
+}
@@ -0,0 +1,937 @@
+//! Binary persistence for [`TFIDFMatcher`].
+//!
+//! A built matcher is written as a fixed header followed by flat little-endian sections, each
+//! starting on an 8-byte boundary (zero-padded):
+//!
+//! ```text
+//! header        magic b"TFIDFMAT", version u32, then the configuration: scoring u8, idf u8,
+//!               normalization u8, flags u8 (case_sensitive, span_words, boundary marker,
+//!               custom analyzer), marker u32, confidence_decimals u8 (0xFF for unrounded),
+//!               tokenization u8, tf u8, 1 reserved byte; then word_weight f64, bm25_k1 f64,
+//!               bm25_b f64, min_ngram u64, max_ngram u64, n_weights u64, n_docs u64,
+//!               fitted_docs u64, total_len u64, n_features u64, nnz u64, n_tags u64,
+//!               n_fields u64
+//! ngram_weights n_weights f64, the per-length n-gram weights
+//! haystack      (n_docs + 1) u64 offsets, then the concatenated UTF-8 strings
+//! terms         (n_features + 1) u64 offsets, then the concatenated terms in feature-index order
+//! sorted_terms  n_features u32 feature indices, ordered by term bytes
+//! doc_freqs     n_features u64
+//! postings      (n_features + 1) u64 offsets, nnz u32 document indices, nnz f64 weights
+//! norms         n_docs f64
+//! weight_sums   n_docs f64
+//! removed       n_docs u8, 1 for entries removed since the index was last built
+//! tag_names     (n_tags + 1) u64 offsets, then the concatenated tags in ascending byte order
+//! tags          n_tags × ⌈n_docs / 64⌉ u64, each tag's bitset of the entries carrying it
+//! field_names   (n_fields + 1) u64 offsets, then the concatenated field names in ascending order
+//! fields        n_fields × n_docs f64, each field's value for every entry, NaN where unset
+//! checksum      u32 CRC-32 (IEEE) of every preceding byte
+//! ```
+//!
+//! Every section's position follows from the header and the offset arrays, so [`Layout::parse`]
+//! locates them in constant time. [`TFIDFMatcher::load`] copies the sections into the in-memory
+//! structures; the memory-mapped matcher queries them in place, looking terms up by binary search
+//! over `sorted_terms`.
+
+use crate::attributes::Attributes;
+use crate::vectorizer::{IdfMethod, TfMethod, Vectorizer, Vocabulary};
+use crate::{
+    Analyzer, Boundary, DocSet, MatcherConfig, MatcherError, NormalizationForm, ScoringVersion,
+    TFIDFMatcher, Tokenization,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"TFIDFMAT";
+
+/// Version of the layout described in the module docs. Bumped whenever the layout of a released
+/// version changes; files with any other version are rejected with
+/// [`MatcherError::UnsupportedVersion`].
+const FORMAT_VERSION: u32 = 1;
+
+const HEADER_LEN: usize = 128;
+/// Start of the 8-byte header fields.
+const HEADER_FIELDS: usize = 24;
+const CHECKSUM_LEN: usize = 4;
+const ALIGN: usize = 8;
+
+/// CRC-32 (IEEE 802.3, reflected) lookup table.
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 0 {
+                crc >> 1
+            } else {
+                0xEDB8_8320 ^ (crc >> 1)
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Incremental CRC-32 over everything written through it.
+#[derive(Debug, Clone, Copy)]
+struct Crc32(u32);
+
+impl Crc32 {
+    fn new() -> Self {
+        Self(!0)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = CRC_TABLE[((self.0 ^ u32::from(byte)) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// Writer that checksums every byte passed through it and tracks its position for alignment.
+struct ChecksumWriter<W> {
+    inner: W,
+    crc: Crc32,
+    position: usize,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.crc.update(bytes);
+        self.position += bytes.len();
+        self.inner.write_all(bytes)
+    }
+
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.bytes(&[value])
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn usize(&mut self, value: usize) -> io::Result<()> {
+        self.u64(value as u64)
+    }
+
+    fn f64(&mut self, value: f64) -> io::Result<()> {
+        self.u64(value.to_bits())
+    }
+
+    /// Zero-pads up to the next section boundary.
+    fn align(&mut self) -> io::Result<()> {
+        let padding = self.position.next_multiple_of(ALIGN) - self.position;
+        self.bytes(&[0; ALIGN][..padding])
+    }
+
+    /// Writes `strings` as `len + 1` cumulative byte offsets followed by their concatenation.
+    fn strings<'s>(&mut self, strings: impl Iterator<Item = &'s str> + Clone) -> io::Result<()> {
+        let mut offset = 0;
+        self.usize(offset)?;
+        for s in strings.clone() {
+            offset += s.len();
+            self.usize(offset)?;
+        }
+        for s in strings {
+            self.bytes(s.as_bytes())?;
+        }
+        self.align()
+    }
+}
+
+fn corrupt(reason: &str) -> MatcherError {
+    MatcherError::Corrupt(reason.to_owned())
+}
+
+fn scoring_tag(scoring: ScoringVersion) -> u8 {
+    match scoring {
+        ScoringVersion::V1 => 1,
+        ScoringVersion::V2 => 2,
+    }
+}
+
+fn scoring_from_tag(tag: u8) -> Result<ScoringVersion, MatcherError> {
+    match tag {
+        1 => Ok(ScoringVersion::V1),
+        2 => Ok(ScoringVersion::V2),
+        _ => Err(corrupt("unknown scoring version")),
+    }
+}
+
+const FLAG_CASE_SENSITIVE: u8 = 1;
+const FLAG_SPAN_WORDS: u8 = 1 << 1;
+const FLAG_MARKER: u8 = 1 << 2;
+/// Set when the index was built with a custom normalizer or tokenizer, which the configuration
+/// alone can't reproduce.
+const FLAG_CUSTOM_ANALYZER: u8 = 1 << 3;
+const UNROUNDED: u8 = u8::MAX;
+
+fn idf_tag(idf: IdfMethod) -> u8 {
+    match idf {
+        IdfMethod::Smooth => 0,
+        IdfMethod::NonSmooth => 1,
+        IdfMethod::Textbook => 2,
+        IdfMethod::Plain => 3,
+        IdfMethod::Probabilistic => 4,
+        IdfMethod::Bm25 => 5,
+    }
+}
+
+fn idf_from_tag(tag: u8) -> Result<IdfMethod, MatcherError> {
+    match tag {
+        0 => Ok(IdfMethod::Smooth),
+        1 => Ok(IdfMethod::NonSmooth),
+        2 => Ok(IdfMethod::Textbook),
+        3 => Ok(IdfMethod::Plain),
+        4 => Ok(IdfMethod::Probabilistic),
+        5 => Ok(IdfMethod::Bm25),
+        _ => Err(corrupt("unknown IDF method")),
+    }
+}
+
+fn tf_tag(tf: TfMethod) -> u8 {
+    match tf {
+        TfMethod::Raw => 0,
+        TfMethod::Sublinear => 1,
+        TfMethod::Binary => 2,
+        TfMethod::Bm25 { .. } => 3,
+    }
+}
+
+/// Decodes the TF method; the BM25 parameters are stored in their own header fields.
+fn tf_from_tag(tag: u8, k1: f64, b: f64) -> Result<TfMethod, MatcherError> {
+    match tag {
+        0 => Ok(TfMethod::Raw),
+        1 => Ok(TfMethod::Sublinear),
+        2 => Ok(TfMethod::Binary),
+        3 => Ok(TfMethod::Bm25 { k1, b }),
+        _ => Err(corrupt("unknown TF method")),
+    }
+}
+
+fn tokenization_tag(tokenization: Tokenization) -> u8 {
+    match tokenization {
+        Tokenization::CharNgrams => 0,
+        Tokenization::Words => 1,
+        Tokenization::Hybrid => 2,
+    }
+}
+
+fn tokenization_from_tag(tag: u8) -> Result<Tokenization, MatcherError> {
+    match tag {
+        0 => Ok(Tokenization::CharNgrams),
+        1 => Ok(Tokenization::Words),
+        2 => Ok(Tokenization::Hybrid),
+        _ => Err(corrupt("unknown tokenization")),
+    }
+}
+
+fn normalization_tag(form: NormalizationForm) -> u8 {
+    match form {
+        NormalizationForm::None => 0,
+        NormalizationForm::Nfc => 1,
+        NormalizationForm::Nfd => 2,
+        NormalizationForm::Nfkc => 3,
+        NormalizationForm::Nfkd => 4,
+    }
+}
+
+fn normalization_from_tag(tag: u8) -> Result<NormalizationForm, MatcherError> {
+    match tag {
+        0 => Ok(NormalizationForm::None),
+        1 => Ok(NormalizationForm::Nfc),
+        2 => Ok(NormalizationForm::Nfd),
+        3 => Ok(NormalizationForm::Nfkc),
+        4 => Ok(NormalizationForm::Nfkd),
+        _ => Err(corrupt("unknown normalization form")),
+    }
+}
+
+/// Writes the configuration fields of the header, which start right after the version.
+fn write_config<W: Write>(
+    w: &mut ChecksumWriter<W>,
+    config: &MatcherConfig,
+    analyzer: &Analyzer,
+) -> io::Result<()> {
+    let mut flags = 0;
+    if analyzer.is_custom() {
+        flags |= FLAG_CUSTOM_ANALYZER;
+    }
+    if config.case_sensitive {
+        flags |= FLAG_CASE_SENSITIVE;
+    }
+    if config.span_words {
+        flags |= FLAG_SPAN_WORDS;
+    }
+    let marker = match config.boundary {
+        Boundary::Marker(marker) => {
+            flags |= FLAG_MARKER;
+            u32::from(marker)
+        }
+        Boundary::None => 0,
+    };
+    w.u8(scoring_tag(config.scoring))?;
+    w.u8(idf_tag(config.idf))?;
+    w.u8(normalization_tag(config.normalization))?;
+    w.u8(flags)?;
+    w.u32(marker)?;
+    w.u8(config.confidence_decimals.map_or(UNROUNDED, |decimals| {
+        u8::try_from(decimals).expect("validated decimals fit in u8")
+    }))?;
+    w.u8(tokenization_tag(config.tokenization))?;
+    w.u8(tf_tag(config.tf))?;
+    w.align()?;
+    let (k1, b) = match config.tf {
+        TfMethod::Bm25 { k1, b } => (k1, b),
+        _ => (0.0, 0.0),
+    };
+    w.f64(config.word_weight)?;
+    w.f64(k1)?;
+    w.f64(b)
+}
+
+/// Decodes the configuration fields of the header, given the n-gram settings stored apart from
+/// them.
+fn read_config(
+    data: &[u8],
+    ngram_range: RangeInclusive<usize>,
+    ngram_weights: Vec<f64>,
+) -> Result<MatcherConfig, MatcherError> {
+    let fields = HEADER_FIELDS..HEADER_LEN;
+    let word_weight = f64_at(data, &fields, 0);
+    let tf = tf_from_tag(data[22], f64_at(data, &fields, 1), f64_at(data, &fields, 2))?;
+    let flags = data[15];
+    let boundary = if flags & FLAG_MARKER == 0 {
+        Boundary::None
+    } else {
+        let marker = u32::from_le_bytes(element(data, &(16..20), 0));
+        Boundary::Marker(char::from_u32(marker).ok_or_else(|| corrupt("invalid boundary marker"))?)
+    };
+    let config = MatcherConfig {
+        tokenization: tokenization_from_tag(data[21])?,
+        word_weight,
+        ngram_range,
+        ngram_weights,
+        case_sensitive: flags & FLAG_CASE_SENSITIVE != 0,
+        normalization: normalization_from_tag(data[14])?,
+        boundary,
+        span_words: flags & FLAG_SPAN_WORDS != 0,
+        idf: idf_from_tag(data[13])?,
+        tf,
+        confidence_decimals: (data[20] != UNROUNDED).then_some(u32::from(data[20])),
+        scoring: scoring_from_tag(data[12])?,
+    };
+    config
+        .validate()
+        .map_err(|err| MatcherError::Corrupt(err.to_string()))?;
+    Ok(config)
+}
+
+/// Reads the `i`-th little-endian `N`-byte element of `section`.
+fn element<const N: usize>(data: &[u8], section: &Range<usize>, i: usize) -> [u8; N] {
+    let start = section.start + i * N;
+    data[start..start + N]
+        .try_into()
+        .expect("slice has element width")
+}
+
+/// Reads an offset or count. Values are bounded by the length of an in-memory buffer, so they fit
+/// in `usize` whenever the data passed [`Layout::parse`].
+#[allow(clippy::cast_possible_truncation)]
+fn usize_at(data: &[u8], section: &Range<usize>, i: usize) -> usize {
+    u64::from_le_bytes(element(data, section, i)) as usize
+}
+
+fn u32_at(data: &[u8], section: &Range<usize>, i: usize) -> u32 {
+    u32::from_le_bytes(element(data, section, i))
+}
+
+fn f64_at(data: &[u8], section: &Range<usize>, i: usize) -> f64 {
+    f64::from_le_bytes(element(data, section, i))
+}
+
+/// Byte ranges of the sections of a persisted matcher, plus its header fields.
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    pub(crate) config: MatcherConfig,
+    /// Whether the index was built with a custom [`Analyzer`].
+    pub(crate) custom_analyzer: bool,
+    pub(crate) n_docs: usize,
+    pub(crate) fitted_docs: usize,
+    pub(crate) total_len: usize,
+    pub(crate) n_features: usize,
+    nnz: usize,
+    n_tags: usize,
+    n_fields: usize,
+    haystack_offsets: Range<usize>,
+    haystack_arena: Range<usize>,
+    term_offsets: Range<usize>,
+    term_arena: Range<usize>,
+    sorted_terms: Range<usize>,
+    doc_freqs: Range<usize>,
+    posting_offsets: Range<usize>,
+    posting_docs: Range<usize>,
+    posting_weights: Range<usize>,
+    norms: Range<usize>,
+    weight_sums: Range<usize>,
+    removed: Range<usize>,
+    tag_offsets: Range<usize>,
+    tag_arena: Range<usize>,
+    tags: Range<usize>,
+    field_offsets: Range<usize>,
+    field_arena: Range<usize>,
+    fields: Range<usize>,
+}
+
+/// Walks the sections in order, bounds-checking each against the data.
+struct Cursor<'d> {
+    data: &'d [u8],
+    position: usize,
+}
+
+impl Cursor<'_> {
+    /// Claims the next `count * width` bytes as a section and skips the padding after it.
+    fn section(&mut self, count: usize, width: usize) -> Result<Range<usize>, MatcherError> {
+        let end = count
+            .checked_mul(width)
+            .and_then(|len| self.position.checked_add(len))
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| corrupt("section extends past the end of the data"))?;
+        let section = self.position..end;
+        self.position = end.next_multiple_of(ALIGN);
+        Ok(section)
+    }
+
+    /// Claims an offsets section of `len + 1` entries and the byte arena it indexes.
+    fn strings(&mut self, len: usize) -> Result<(Range<usize>, Range<usize>), MatcherError> {
+        let offsets = self.section(
+            len.checked_add(1)
+                .ok_or_else(|| corrupt("length overflows"))?,
+            8,
+        )?;
+        let arena_len = usize::try_from(u64::from_le_bytes(element(self.data, &offsets, len)))
+            .map_err(|_| corrupt("length exceeds usize"))?;
+        let arena = self.section(arena_len, 1)?;
+        Ok((offsets, arena))
+    }
+}
+
+impl Layout {
+    /// Checks the header and locates every section of `data` without touching the section
+    /// contents, so it runs in constant time. The checksum is verified separately by
+    /// [`verify_checksum`](Self::verify_checksum) and the contents by [`validate`](Self::validate).
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, MatcherError> {
+        Self::check_header(data)?;
+        let field = |i| {
+            usize::try_from(u64::from_le_bytes(element(
+                data,
+                &(HEADER_FIELDS..HEADER_LEN),
+                i,
+            )))
+            .map_err(|_| corrupt("length exceeds usize"))
+        };
+        let ngram_range = field(3)?..=field(4)?;
+        let n_weights = field(5)?;
+        let n_docs = field(6)?;
+        let fitted_docs = field(7)?;
+        let total_len = field(8)?;
+        let n_features = field(9)?;
+        let nnz = field(10)?;
+        let n_tags = field(11)?;
+        let n_fields = field(12)?;
+
+        let body = &data[..data.len() - CHECKSUM_LEN];
+        let mut cursor = Cursor {
+            data: body,
+            position: HEADER_LEN,
+        };
+        let ngram_weights = cursor.section(n_weights, 8)?;
+        let ngram_weights = (0..n_weights)
+            .map(|i| f64_at(body, &ngram_weights, i))
+            .collect();
+        let config = read_config(data, ngram_range, ngram_weights)?;
+        let (haystack_offsets, haystack_arena) = cursor.strings(n_docs)?;
+        let (term_offsets, term_arena) = cursor.strings(n_features)?;
+        let sorted_terms = cursor.section(n_features, 4)?;
+        let doc_freqs = cursor.section(n_features, 8)?;
+        let posting_offsets = cursor.section(n_features.saturating_add(1), 8)?;
+        let posting_docs = cursor.section(nnz, 4)?;
+        let posting_weights = cursor.section(nnz, 8)?;
+        let norms = cursor.section(n_docs, 8)?;
+        let weight_sums = cursor.section(n_docs, 8)?;
+        let removed = cursor.section(n_docs, 1)?;
+        let (tag_offsets, tag_arena) = cursor.strings(n_tags)?;
+        let tags = cursor.section(
+            n_tags
+                .checked_mul(n_docs.div_ceil(64))
+                .ok_or_else(|| corrupt("length overflows"))?,
+            8,
+        )?;
+        let (field_offsets, field_arena) = cursor.strings(n_fields)?;
+        let fields = cursor.section(
+            n_fields
+                .checked_mul(n_docs)
+                .ok_or_else(|| corrupt("length overflows"))?,
+            8,
+        )?;
+        if cursor.position != body.len() {
+            return Err(corrupt("trailing data after the last section"));
+        }
+
+        Ok(Self {
+            config,
+            custom_analyzer: data[15] & FLAG_CUSTOM_ANALYZER != 0,
+            n_docs,
+            fitted_docs,
+            total_len,
+            n_features,
+            nnz,
+            n_tags,
+            n_fields,
+            haystack_offsets,
+            haystack_arena,
+            term_offsets,
+            term_arena,
+            sorted_terms,
+            doc_freqs,
+            posting_offsets,
+            posting_docs,
+            posting_weights,
+            norms,
+            weight_sums,
+            removed,
+            tag_offsets,
+            tag_arena,
+            tags,
+            field_offsets,
+            field_arena,
+            fields,
+        })
+    }
+
+    /// The analyzer to query the index with: `supplied`, or else the default pipeline for the
+    /// saved configuration, provided the index didn't use a custom one.
+    pub(crate) fn analyzer(&self, supplied: Option<Analyzer>) -> Result<Analyzer, MatcherError> {
+        match supplied {
+            Some(analyzer) => Ok(analyzer),
+            None if self.custom_analyzer => Err(MatcherError::InvalidConfig(
+                "the index was built with a custom normalizer or tokenizer, which must be supplied \
+                 when loading it"
+                    .to_owned(),
+            )),
+            None => Ok(Analyzer::from_config(&self.config)),
+        }
+    }
+
+    /// Checks the magic bytes and format version, and that `data` is long enough to hold a header.
+    pub(crate) fn check_header(data: &[u8]) -> Result<(), MatcherError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(MatcherError::NotAMatcherFile);
+        }
+        if data.len() < HEADER_LEN + CHECKSUM_LEN {
+            return Err(corrupt("unexpected end of data"));
+        }
+        let version = u32::from_le_bytes(element(data, &(8..12), 0));
+        if version != FORMAT_VERSION {
+            return Err(MatcherError::UnsupportedVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        Ok(())
+    }
+
+    /// Compares the trailing checksum against one computed over the rest of `data`, which must
+    /// have passed [`check_header`](Self::check_header).
+    pub(crate) fn verify_checksum(data: &[u8]) -> Result<(), MatcherError> {
+        let (body, stored) = data.split_at(data.len() - CHECKSUM_LEN);
+        let stored = u32::from_le_bytes(stored.try_into().expect("checksum is 4 bytes"));
+        let mut crc = Crc32::new();
+        crc.update(body);
+        let computed = crc.finish();
+        if stored == computed {
+            Ok(())
+        } else {
+            Err(MatcherError::ChecksumMismatch { stored, computed })
+        }
+    }
+
+    /// Checks the section contents: offsets are monotonic and within their arenas, strings are
+    /// UTF-8, `sorted_terms` is a permutation in term order, postings refer to existing documents,
+    /// tag and field names are strictly ordered, and tags are carried by existing documents only.
+    /// Once this passes, none of the accessors below can fail.
+    pub(crate) fn validate(&self, data: &[u8]) -> Result<(), MatcherError> {
+        let strings = |offsets: &Range<usize>, arena: &Range<usize>, len: usize| {
+            let mut previous = 0;
+            for i in 0..=len {
+                let offset = usize_at(data, offsets, i);
+                if (i == 0 && offset != 0) || offset < previous {
+                    return Err(corrupt("offsets are not monotonic"));
+                }
+                previous = offset;
+            }
+            let arena = std::str::from_utf8(&data[arena.clone()])
+                .map_err(|_| corrupt("string is not valid UTF-8"))?;
+            if (0..=len).any(|i| !arena.is_char_boundary(usize_at(data, offsets, i))) {
+                return Err(corrupt("string offset is not on a character boundary"));
+            }
+            Ok(())
+        };
+        strings(&self.haystack_offsets, &self.haystack_arena, self.n_docs)?;
+        strings(&self.term_offsets, &self.term_arena, self.n_features)?;
+        strings(&self.tag_offsets, &self.tag_arena, self.n_tags)?;
+        strings(&self.field_offsets, &self.field_arena, self.n_fields)?;
+        let ordered = |offsets: &Range<usize>, arena: &Range<usize>, len: usize| {
+            (1..len).all(|i| {
+                Self::string(data, offsets, arena, i - 1) < Self::string(data, offsets, arena, i)
+            })
+        };
+        if !ordered(&self.tag_offsets, &self.tag_arena, self.n_tags)
+            || !ordered(&self.field_offsets, &self.field_arena, self.n_fields)
+        {
+            return Err(corrupt("attribute names are not strictly ordered"));
+        }
+
+        let mut seen = vec![false; self.n_features];
+        for rank in 0..self.n_features {
+            let feature = u32_at(data, &self.sorted_terms, rank) as usize;
+            if feature >= self.n_features || std::mem::replace(&mut seen[feature], true) {
+                return Err(corrupt("term order is not a permutation"));
+            }
+            if rank > 0 {
+                let previous = u32_at(data, &self.sorted_terms, rank - 1) as usize;
+                if self.term(data, previous) >= self.term(data, feature) {
+                    return Err(corrupt("terms are not strictly ordered"));
+                }
+            }
+        }
+
+        let mut previous = 0;
+        for feature in 0..=self.n_features {
+            let offset = usize_at(data, &self.posting_offsets, feature);
+            if (feature == 0 && offset != 0) || offset < previous {
+                return Err(corrupt("offsets are not monotonic"));
+            }
+            previous = offset;
+        }
+        if previous != self.nnz {
+            return Err(corrupt("posting offsets don't cover the postings"));
+        }
+        if (0..self.nnz).any(|i| u32_at(data, &self.posting_docs, i) as usize >= self.n_docs) {
+            return Err(corrupt("posting refers to a document out of range"));
+        }
+        if data[self.removed.clone()].iter().any(|&flag| flag > 1) {
+            return Err(corrupt("invalid removal flag"));
+        }
+        let words = self.n_docs.div_ceil(64);
+        if !self.n_docs.is_multiple_of(64) {
+            let past_end = !0u64 << (self.n_docs % 64);
+            if (0..self.n_tags).any(|tag| {
+                u64::from_le_bytes(element(data, &self.tags, tag * words + words - 1)) & past_end
+                    != 0
+            }) {
+                return Err(corrupt("tag refers to a document out of range"));
+            }
+        }
+        Ok(())
+    }
+
+    fn string<'d>(
+        data: &'d [u8],
+        offsets: &Range<usize>,
+        arena: &Range<usize>,
+        i: usize,
+    ) -> &'d [u8] {
+        let start = arena.start + usize_at(data, offsets, i);
+        let end = arena.start + usize_at(data, offsets, i + 1);
+        &data[start..end]
+    }
+
+    /// The haystack string of `doc`.
+    ///
+    /// # Panics
+    /// Panics if the data is not valid UTF-8, which [`validate`](Self::validate) rules out.
+    pub(crate) fn haystack<'d>(&self, data: &'d [u8], doc: usize) -> &'d str {
+        let bytes = Self::string(data, &self.haystack_offsets, &self.haystack_arena, doc);
+        std::str::from_utf8(bytes).expect("haystack entry is not valid UTF-8")
+    }
+
+    fn term<'d>(&self, data: &'d [u8], feature: usize) -> &'d [u8] {
+        Self::string(data, &self.term_offsets, &self.term_arena, feature)
+    }
+
+    /// Binary-searches the term order for `token`'s feature index.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn feature(&self, data: &[u8], token: &str) -> Option<usize> {
+        let (mut low, mut high) = (0, self.n_features);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let feature = u32_at(data, &self.sorted_terms, mid) as usize;
+            match self.term(data, feature).cmp(token.as_bytes()) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(feature),
+            }
+        }
+        None
+    }
+
+    pub(crate) fn doc_freq(&self, data: &[u8], feature: usize) -> usize {
+        usize_at(data, &self.doc_freqs, feature)
+    }
+
+    /// The `(document, weight)` postings of `feature`.
+    pub(crate) fn postings<'d>(
+        &self,
+        data: &'d [u8],
+        feature: usize,
+    ) -> impl Iterator<Item = (u32, f64)> + 'd {
+        let start = usize_at(data, &self.posting_offsets, feature);
+        let end = usize_at(data, &self.posting_offsets, feature + 1);
+        let (docs, weights) = (self.posting_docs.clone(), self.posting_weights.clone());
+        (start..end).map(move |i| (u32_at(data, &docs, i), f64_at(data, &weights, i)))
+    }
+
+    pub(crate) fn norm(&self, data: &[u8], doc: usize) -> f64 {
+        f64_at(data, &self.norms, doc)
+    }
+
+    pub(crate) fn weight_sum(&self, data: &[u8], doc: usize) -> f64 {
+        f64_at(data, &self.weight_sums, doc)
+    }
+
+    pub(crate) fn is_removed(&self, data: &[u8], doc: usize) -> bool {
+        data[self.removed.start + doc] != 0
+    }
+
+    /// Decodes the tags and numeric fields of every document.
+    pub(crate) fn attributes(&self, data: &[u8]) -> Attributes {
+        let name = |offsets, arena, i| {
+            String::from_utf8_lossy(Self::string(data, offsets, arena, i)).into_owned()
+        };
+        let words = self.n_docs.div_ceil(64);
+        let tags = (0..self.n_tags).map(|tag| {
+            let set = (0..words)
+                .map(|word| u64::from_le_bytes(element(data, &self.tags, tag * words + word)))
+                .collect();
+            (
+                name(&self.tag_offsets, &self.tag_arena, tag),
+                DocSet::from_words(set),
+            )
+        });
+        let fields = (0..self.n_fields).map(|field| {
+            let values = (0..self.n_docs)
+                .map(|doc| f64_at(data, &self.fields, field * self.n_docs + doc))
+                .collect();
+            (name(&self.field_offsets, &self.field_arena, field), values)
+        });
+        Attributes {
+            tags: tags.filter(|(_, docs)| !docs.is_empty()).collect(),
+            fields: fields.collect(),
+        }
+    }
+}
+
+impl TFIDFMatcher {
+    /// Writes the matcher to `writer` in a versioned, checksummed binary format.
+    ///
+    /// The output holds everything [`load`](Self::load) needs to answer queries without
+    /// re-vectorizing the haystack: the haystack itself, the vocabulary, document frequencies, the
+    /// inverted index, the document norms and weight sums, the tombstones of removed entries, and
+    /// the entries' tags and numeric fields.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if writing fails.
+    ///
+    /// # Panics
+    /// Panics if the vocabulary has more than `u32::MAX` features.
+    pub fn save<W: Write>(&self, writer: W) -> Result<(), MatcherError> {
+        let mut w = ChecksumWriter {
+            inner: BufWriter::new(writer),
+            crc: Crc32::new(),
+            position: 0,
+        };
+        let terms = self.fitted.terms();
+        let mut sorted_terms: Vec<usize> = (0..terms.len()).collect();
+        sorted_terms.sort_unstable_by_key(|&feature| terms[feature]);
+        let nnz: usize = self.postings.iter().map(Vec::len).sum();
+
+        w.bytes(MAGIC)?;
+        w.u32(FORMAT_VERSION)?;
+        write_config(&mut w, &self.config, &self.analyzer)?;
+        w.usize(*self.config.ngram_range.start())?;
+        w.usize(*self.config.ngram_range.end())?;
+        w.usize(self.config.ngram_weights.len())?;
+        w.usize(self.n_docs)?;
+        w.usize(self.fitted.fitted_docs())?;
+        w.usize(self.fitted.total_len())?;
+        w.usize(terms.len())?;
+        w.usize(nnz)?;
+        w.usize(self.attributes.tags.len())?;
+        w.usize(self.attributes.fields.len())?;
+        for &weight in &self.config.ngram_weights {
+            w.f64(weight)?;
+        }
+
+        w.strings(self.haystack.iter().map(String::as_str))?;
+        w.strings(terms.iter().copied())?;
+        for &feature in &sorted_terms {
+            w.u32(u32::try_from(feature).expect("vocabulary exceeds u32"))?;
+        }
+        w.align()?;
+        for &df in self.fitted.doc_freqs() {
+            w.usize(df)?;
+        }
+
+        let mut offset = 0;
+        w.usize(offset)?;
+        for list in &self.postings {
+            offset += list.len();
+            w.usize(offset)?;
+        }
+        for &(doc, _) in self.postings.iter().flatten() {
+            w.u32(doc)?;
+        }
+        w.align()?;
+        for &(_, weight) in self.postings.iter().flatten() {
+            w.f64(weight)?;
+        }
+        for &norm in &self.haystack_norm {
+            w.f64(norm)?;
+        }
+        for &sum in &self.haystack_sum {
+            w.f64(sum)?;
+        }
+        for &removed in &self.removed {
+            w.u8(u8::from(removed))?;
+        }
+        w.align()?;
+
+        w.strings(self.attributes.tags.keys().map(String::as_str))?;
+        let words = self.n_docs.div_ceil(64);
+        for docs in self.attributes.tags.values() {
+            let set = docs.words();
+            for word in 0..words {
+                w.u64(set.get(word).copied().unwrap_or(0))?;
+            }
+        }
+        w.strings(self.attributes.fields.keys().map(String::as_str))?;
+        for values in self.attributes.fields.values() {
+            for doc in 0..self.n_docs {
+                w.f64(values.get(doc).copied().unwrap_or(f64::NAN))?;
+            }
+        }
+
+        let checksum = w.crc.finish();
+        w.inner.write_all(&checksum.to_le_bytes())?;
+        w.inner.flush()?;
+        Ok(())
+    }
+
+    /// Writes the matcher to the file at `path`, creating or truncating it.
+    ///
+    /// # Errors
+    /// Returns [`MatcherError::Io`] if the file can't be created or written.
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> Result<(), MatcherError> {
+        self.save(File::create(path)?)
+    }
+
+    /// Reads a matcher previously written by [`save`](Self::save).
+    ///
+    /// # Errors
+    /// * [`MatcherError::NotAMatcherFile`] if the data doesn't start with the expected magic bytes;
+    /// * [`MatcherError::UnsupportedVersion`] if it was written in another format version;
+    /// * [`MatcherError::ChecksumMismatch`] if its contents don't match the stored checksum, which
+    ///   includes data truncated past the header;
+    /// * [`MatcherError::Corrupt`] if it is too short to hold a header or structurally
+    ///   inconsistent;
+    /// * [`MatcherError::InvalidConfig`] if the matcher was built with a custom normalizer or
+    ///   tokenizer, which must be supplied through [`load_with`](Self::load_with);
+    /// * [`MatcherError::Io`] if reading fails.
+    pub fn load<R: Read>(reader: R) -> Result<Self, MatcherError> {
+        Self::read(reader, None)
+    }
+
+    /// Reads a matcher previously written by [`save`](Self::save), analyzing needles and new
+    /// entries with `analyzer` instead of the pipeline described by the saved configuration.
+    ///
+    /// `analyzer` must produce the same tokens as the one the matcher was built with, or queries
+    /// will silently miss.
+    ///
+    /// # Errors
+    /// As for [`load`](Self::load), except that custom analyzers are accepted.
+    pub fn load_with<R: Read>(reader: R, analyzer: Analyzer) -> Result<Self, MatcherError> {
+        Self::read(reader, Some(analyzer))
+    }
+
+    fn read<R: Read>(mut reader: R, analyzer: Option<Analyzer>) -> Result<Self, MatcherError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Layout::check_header(&data)?;
+        Layout::verify_checksum(&data)?;
+        let layout = Layout::parse(&data)?;
+        layout.validate(&data)?;
+        let analyzer = layout.analyzer(analyzer)?;
+
+        let terms = (0..layout.n_features)
+            .map(|feature| String::from_utf8_lossy(layout.term(&data, feature)).into_owned())
+            .collect();
+        let doc_freqs = (0..layout.n_features)
+            .map(|feature| layout.doc_freq(&data, feature))
+            .collect();
+        let fitted = Vectorizer::from_parts(
+            terms,
+            layout.fitted_docs,
+            doc_freqs,
+            layout.total_len,
+            layout.config.idf,
+            layout.config.tf,
+        )
+        .ok_or_else(|| corrupt("vocabulary contains duplicate terms"))?;
+
+        Ok(Self {
+            haystack: (0..layout.n_docs)
+                .map(|doc| layout.haystack(&data, doc).to_owned())
+                .collect(),
+            fitted,
+            postings: (0..layout.n_features)
+                .map(|feature| layout.postings(&data, feature).collect())
+                .collect(),
+            haystack_norm: (0..layout.n_docs)
+                .map(|doc| layout.norm(&data, doc))
+                .collect(),
+            haystack_sum: (0..layout.n_docs)
+                .map(|doc| layout.weight_sum(&data, doc))
+                .collect(),
+            removed: (0..layout.n_docs)
+                .map(|doc| layout.is_removed(&data, doc))
+                .collect(),
+            attributes: layout.attributes(&data),
+            n_docs: layout.n_docs,
+            config: layout.config,
+            analyzer,
+        })
+    }
+
+    /// Reads a matcher from the file at `path` (see [`load`](Self::load)).
+    ///
+    /// # Errors
+    /// See [`load`](Self::load).
+    pub fn load_from_path(path: impl AsRef<Path>) -> Result<Self, MatcherError> {
+        Self::load(File::open(path)?)
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-query options: how candidates are scored and which of them are kept.
+//!
+//! Every measure is computed from the same postings traversal. Cosine accumulates the dot product
+//! `Σ q·d`; the set-overlap measures accumulate the weighted intersection `Σ min(q, d)` and combine
+//! it with the total weights `Σ q` and `Σ d` of the needle and document vectors. The needle's
+//! total includes its n-grams no entry contains, weighted with the IDF of an unseen feature.
+
+use crate::Filter;
+
+/// How the similarity between a needle and a haystack entry is measured. Every measure lies between
+/// 0 and 1 and is reported in [`MatchEntry::confidence`](crate::MatchEntry::confidence).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Similarity {
+    /// Cosine of the angle between the TF-IDF vectors, `Σ q·d / (‖q‖ ‖d‖)`.
+    #[default]
+    Cosine,
+    /// Weighted Jaccard index, `Σ min(q, d) / Σ max(q, d)`.
+    Jaccard,
+    /// Weighted Sørensen–Dice coefficient, `2 Σ min(q, d) / (Σ q + Σ d)`.
+    Dice,
+    /// Weighted overlap coefficient, `Σ min(q, d) / min(Σ q, Σ d)`: 1 when either string's
+    /// features are contained in the other's.
+    Overlap,
+    /// How much of the needle is contained in the haystack entry, `Σ min(q, d) / Σ q`.
+    Containment,
+}
+
+impl Similarity {
+    /// The per-feature contribution of needle weight `q` and document weight `d` to the
+    /// accumulated score.
+    #[inline]
+    pub(crate) fn accumulate(self, q: f64, d: f64) -> f64 {
+        match self {
+            Self::Cosine => q * d,
+            _ => q.min(d),
+        }
+    }
+
+    /// Turns an accumulated score into the similarity, given the L2 norms and weight sums of the
+    /// needle (`q_*`) and document (`d_*`) vectors.
+    #[inline]
+    pub(crate) fn finish(self, acc: f64, q_norm: f64, q_sum: f64, d_norm: f64, d_sum: f64) -> f64 {
+        let denom = match self {
+            Self::Cosine => q_norm * d_norm,
+            Self::Jaccard => q_sum + d_sum - acc,
+            Self::Dice => f64::midpoint(q_sum, d_sum),
+            Self::Overlap => q_sum.min(d_sum),
+            Self::Containment => q_sum,
+        };
+        if denom == 0.0 { 0.0 } else { acc / denom }
+    }
+}
+
+/// Options for [`TFIDFMatcher::find_with`](crate::TFIDFMatcher::find_with) and
+/// [`TFIDFMatcher::find_many_with`](crate::TFIDFMatcher::find_many_with).
+///
+/// ```
+/// use tf_idf_matcher::{FindOptions, Similarity, TFIDFMatcher};
+///
+/// let matcher = TFIDFMatcher::new(["Acme Corporation", "Acme"], 3)?;
+/// let options = FindOptions::new().similarity(Similarity::Containment);
+/// let result = matcher.find_with("acme", 2, &options)?;
+/// assert_eq!(result.matches[0].confidence, 1.0);
+/// # Ok::<(), tf_idf_matcher::MatcherError>(())
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[non_exhaustive]
+#[must_use]
+pub struct FindOptions {
+    /// How candidates are scored.
+    pub similarity: Similarity,
+    /// Drop matches scoring below this similarity. The unrounded similarity is compared, so a match
+    /// just below the threshold is dropped even if its confidence rounds up to it.
+    pub min_confidence: Option<f64>,
+    /// Restricts matches to the haystack entries the filter admits. Not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub filter: Option<Filter>,
+}
+
+impl FindOptions {
+    /// The default options: cosine similarity, no threshold, no filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the similarity measure.
+    pub fn similarity(mut self, similarity: Similarity) -> Self {
+        self.similarity = similarity;
+        self
+    }
+
+    /// Keeps only matches with a similarity of at least `min_confidence`.
+    pub fn min_confidence(mut self, min_confidence: f64) -> Self {
+        self.min_confidence = Some(min_confidence);
+        self
+    }
+
+    /// Restricts matches to the haystack entries `filter` admits.
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Whether a match with similarity `sim` passes the threshold.
+    #[inline]
+    pub(crate) fn accepts(&self, sim: f64) -> bool {
+        self.min_confidence.is_none_or(|min| sim >= min)
+    }
+}
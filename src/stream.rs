@@ -0,0 +1,98 @@
+//! Lazily matching needles drawn from an iterator, a chunk at a time.
+
+use crate::{FindOptions, Index, MatchEntry, Normalize};
+use std::collections::VecDeque;
+use std::fmt;
+
+/// Number of needles vectorized together by default; see [`FindIter::chunk_size`].
+const DEFAULT_CHUNK_SIZE: usize = 256;
+
+/// An iterator over the matches of needles pulled lazily from another iterator, returned by
+/// [`TFIDFMatcher::find_iter`](crate::TFIDFMatcher::find_iter).
+///
+/// Needles are consumed and vectorized in chunks of [`chunk_size`](Self::chunk_size), so memory
+/// stays bounded by one chunk however many needles the source yields. Each item pairs a needle
+/// with its top-k matches, in the order the needles were drawn.
+///
+/// Under [`ScoringVersion::V1`](crate::ScoringVersion::V1) the IDF is recomputed per chunk, so
+/// scores depend on how needles fall into chunks. Under the default
+/// [`ScoringVersion::V2`](crate::ScoringVersion::V2) they are identical to
+/// [`find`](crate::TFIDFMatcher::find).
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct FindIter<'a, M, I: Iterator> {
+    matcher: &'a M,
+    needles: I,
+    top_k: usize,
+    options: FindOptions,
+    chunk_size: usize,
+    ready: VecDeque<(I::Item, Vec<MatchEntry<'a>>)>,
+}
+
+impl<'a, M, I: Iterator> FindIter<'a, M, I> {
+    pub(crate) fn new(matcher: &'a M, needles: I, top_k: usize, options: FindOptions) -> Self {
+        Self {
+            matcher,
+            needles,
+            top_k,
+            options,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Sets how many needles are drawn and vectorized at once (default 256, at least 1).
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+impl<'a, M, I> Iterator for FindIter<'a, M, I>
+where
+    M: Index,
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    type Item = (I::Item, Vec<MatchEntry<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ready.is_empty() {
+            let chunk: Vec<I::Item> = self.needles.by_ref().take(self.chunk_size).collect();
+            let ngrams: Vec<Vec<String>> = chunk
+                .iter()
+                .map(|needle| self.matcher.analyzer().analyze(needle.as_ref()))
+                .collect();
+            let tfidf = self.matcher.vectorize_needles(&ngrams);
+            let norms = tfidf.normalize();
+            let rows = tfidf.outer_iterator().zip(norms).zip(&ngrams);
+            for (needle, ((row, norm), needle_ngrams)) in chunk.into_iter().zip(rows) {
+                let q_sum = self.matcher.needle_sum(needle_ngrams, row);
+                let matches =
+                    self.matcher
+                        .top_k_matches(row, norm, q_sum, self.top_k, &self.options);
+                self.ready.push_back((needle, matches));
+            }
+        }
+        self.ready.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.needles.size_hint();
+        let ready = self.ready.len();
+        (
+            lower.saturating_add(ready),
+            upper.and_then(|upper| upper.checked_add(ready)),
+        )
+    }
+}
+
+impl<M, I: Iterator> fmt::Debug for FindIter<'_, M, I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FindIter")
+            .field("top_k", &self.top_k)
+            .field("options", &self.options)
+            .field("chunk_size", &self.chunk_size)
+            .field("ready", &self.ready.len())
+            .finish_non_exhaustive()
+    }
+}
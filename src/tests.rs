@@ -8,26 +8,104 @@ fn matcher_is_send_sync() {
     assert_send_sync::<TFIDFMatcher>();
 }
 
+/// The analyzed n-grams of `text` under the default configuration, space-separated.
+fn text_into_ngrams(text: &str, n: usize) -> String {
+    let config = MatcherConfig {
+        ngram_range: n..=n,
+        ..MatcherConfig::default()
+    };
+    Analyzer::from_config(&config).analyze(text).join(" ")
+}
+
 #[test]
 fn test_text_into_ngrams() {
-    let result = TFIDFMatcher::text_into_ngrams("abcde", 2);
+    let result = text_into_ngrams("abcde", 2);
     assert_eq!(result, "_a ab bc cd de e_");
 
-    let result = TFIDFMatcher::text_into_ngrams("abc de", 2);
+    let result = text_into_ngrams("abc de", 2);
     assert_eq!(result, "_a ab bc c_ _d de e_");
 
-    let result = TFIDFMatcher::text_into_ngrams("lets get rusty", 3);
+    let result = text_into_ngrams("lets get rusty", 3);
     assert_eq!(result, "_le let ets ts_ _ge get et_ _ru rus ust sty ty_");
 }
 
+/// The default analysis reproduces the n-grams of 0.2, which lowercased, padded words with `_`
+/// (treating a literal `_` as a boundary too) and then NFKD-normalized each n-gram.
+#[test]
+fn test_default_analysis_matches_baseline() {
+    let analyzer = |n| TFIDFMatcher::builder().ngram_length(n).analyzer();
+    let cases: [(&str, usize, &[&str]); 5] = [
+        (
+            "ﬁnance Ǆemal",
+            3,
+            &[
+                "_fin",
+                "fina",
+                "nan",
+                "anc",
+                "nce",
+                "ce_",
+                "_dz\u{30c}e",
+                "dz\u{30c}em",
+                "ema",
+                "mal",
+                "al_",
+            ],
+        ),
+        ("foo_bar", 3, &["_fo", "foo", "oo_", "_ba", "bar", "ar_"]),
+        (
+            "Ωhm ℌello",
+            2,
+            &["_ω", "ωh", "hm", "m_", "_h", "he", "el", "ll", "lo", "o_"],
+        ),
+        (
+            "O´Brien",
+            3,
+            &[
+                "_o",
+                "\u{301}",
+                "o",
+                "\u{301}b",
+                "\u{301}br",
+                "bri",
+                "rie",
+                "ien",
+                "en_",
+            ],
+        ),
+        ("ΣΑΣ", 3, &["_σα", "σασ", "ασ_"]),
+    ];
+    for (text, n, expected) in cases {
+        assert_eq!(analyzer(n).analyze(text), expected, "{text}");
+    }
+
+    // Scores under V1 are those 0.2 reported.
+    let matcher = TFIDFMatcher::with_scoring_version(
+        ["foo_bar", "foo bar", "ﬁnance Ǆemal", "finance dzemal"],
+        3,
+        ScoringVersion::V1,
+    )
+    .expect("Failed to create matcher");
+    let ranked = |needle| {
+        let result = matcher.find(needle, 2).expect("find failed");
+        result
+            .matches
+            .iter()
+            .map(|m| (m.haystack_idx, m.confidence))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(ranked("foo bar"), [(0, 1.0), (1, 1.0)]);
+    assert_eq!(ranked("ﬁnance"), [(2, 0.73), (3, 0.4)]);
+}
+
 #[test]
 fn test_ngrams_shorter_than_n() {
-    assert_eq!(TFIDFMatcher::text_into_ngrams("a", 2), "_a a_");
+    assert_eq!(text_into_ngrams("a", 2), "_a a_");
 }
 
 #[test]
 fn test_text_into_ngrams_lowercase_and_join() {
-    let result = TFIDFMatcher::text_into_ngrams("AbCd", 2);
+    let result = text_into_ngrams("AbCd", 2);
     assert_eq!(result, "_a ab bc cd d_");
 }
 
@@ -113,3 +191,1350 @@ fn test_bench() {
     println!("{result:?}");
     assert_eq!(result.matches[0].haystack, "Vladimir Putin");
 }
+
+#[test]
+fn test_find_and_find_many_score_identically() {
+    let matcher = TFIDFMatcher::new(["test", "testing", "example", "examine"], 3)
+        .expect("Failed to create matcher");
+    let single = matcher.find("testin", 4).expect("find failed");
+    let alone = matcher.find_many(["testin"], 4).expect("find_many failed");
+    let batched = matcher
+        .find_many(["exam", "testin", "test"], 4)
+        .expect("find_many failed");
+
+    for other in [&alone[0], &batched[1]] {
+        assert_eq!(other.matches.len(), single.matches.len());
+        for (a, b) in single.matches.iter().zip(&other.matches) {
+            assert_eq!(a.haystack_idx, b.haystack_idx);
+            assert_eq!(a.confidence.to_bits(), b.confidence.to_bits());
+        }
+    }
+}
+
+#[test]
+fn test_scoring_v1_keeps_batch_idf() {
+    let haystack = ["test", "testing", "example", "examine"];
+    let v1 = TFIDFMatcher::with_scoring_version(haystack, 3, ScoringVersion::V1)
+        .expect("Failed to create matcher");
+    assert_eq!(v1.scoring_version(), ScoringVersion::V1);
+
+    // Under batch IDF the same needle scores differently depending on its batch.
+    let alone = v1.find_many(["testin"], 1).expect("find_many failed");
+    let batched = v1
+        .find_many(["testin", "tester", "test", "tests"], 1)
+        .expect("find_many failed");
+    assert!((alone[0].matches[0].confidence - batched[0].matches[0].confidence).abs() > 1e-8);
+}
+
+fn saved_bytes(matcher: &TFIDFMatcher) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    matcher.save(&mut bytes).expect("save failed");
+    bytes
+}
+
+#[test]
+fn test_save_load_roundtrip() {
+    let matcher = TFIDFMatcher::new(["Joe Biden", "Donald Trump", "Barack Obama"], 3)
+        .expect("Failed to create matcher");
+    let bytes = saved_bytes(&matcher);
+    let loaded = TFIDFMatcher::load(bytes.as_slice()).expect("load failed");
+
+    for needle in ["biden joe", "trump", "obama barack"] {
+        let expected = matcher.find(needle, 3).expect("find failed");
+        let actual = loaded.find(needle, 3).expect("find failed");
+        assert_eq!(expected.matches.len(), actual.matches.len());
+        for (a, b) in expected.matches.iter().zip(&actual.matches) {
+            assert_eq!(a.haystack, b.haystack);
+            assert_eq!(a.haystack_idx, b.haystack_idx);
+            assert_eq!(a.confidence.to_bits(), b.confidence.to_bits());
+        }
+    }
+    // Saving the loaded matcher reproduces the original bytes.
+    assert_eq!(saved_bytes(&loaded), bytes);
+}
+
+#[test]
+fn test_load_rejects_bad_input() {
+    let matcher = TFIDFMatcher::new(["test", "testing"], 3).expect("Failed to create matcher");
+    let bytes = saved_bytes(&matcher);
+
+    let mut flipped = bytes.clone();
+    flipped[bytes.len() / 2] ^= 0xFF;
+    assert!(matches!(
+        TFIDFMatcher::load(flipped.as_slice()),
+        Err(MatcherError::ChecksumMismatch { .. })
+    ));
+
+    let mut future = bytes.clone();
+    future[8] = 0xFF;
+    assert!(matches!(
+        TFIDFMatcher::load(future.as_slice()),
+        Err(MatcherError::UnsupportedVersion { .. })
+    ));
+
+    assert!(matches!(
+        TFIDFMatcher::load(&b"not a matcher"[..]),
+        Err(MatcherError::NotAMatcherFile)
+    ));
+    assert!(matches!(
+        TFIDFMatcher::load(&bytes[..20]),
+        Err(MatcherError::Corrupt(_))
+    ));
+    assert!(matches!(
+        TFIDFMatcher::load(&bytes[..bytes.len() - 7]),
+        Err(MatcherError::ChecksumMismatch { .. })
+    ));
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn test_mapped_matcher_matches_in_memory() {
+    let haystack = ["Joe Biden", "Donald Trump", "Barack Obama", "Angela Merkel"];
+    let matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let path = std::env::temp_dir().join(format!("tf-idf-matcher-{}.idx", std::process::id()));
+    matcher.save_to_path(&path).expect("save failed");
+
+    // SAFETY: the file is private to this test and not modified while mapped.
+    let mapped = unsafe { MappedMatcher::open(&path) }.expect("open failed");
+    mapped.verify().expect("verify failed");
+    assert_eq!(mapped.len(), haystack.len());
+
+    let needles = ["obama", "merkel angela", "joe", "nothing in common"];
+    let expected = matcher.find_many(needles, 3).expect("find_many failed");
+    let actual = mapped.find_many(needles, 3).expect("find_many failed");
+    for (e, a) in expected.iter().zip(&actual) {
+        assert_eq!(e.matches.len(), a.matches.len());
+        for (x, y) in e.matches.iter().zip(&a.matches) {
+            assert_eq!(x.haystack, y.haystack);
+            assert_eq!(x.haystack_idx, y.haystack_idx);
+            assert_eq!(x.confidence.to_bits(), y.confidence.to_bits());
+        }
+    }
+    let single = mapped.find("obama", 1).expect("find failed");
+    assert_eq!(single.matches[0].haystack, "Barack Obama");
+
+    drop(mapped);
+    std::fs::remove_file(&path).expect("cleanup failed");
+}
+
+#[test]
+fn test_insert_and_remove_keep_indices_stable() {
+    let mut matcher = TFIDFMatcher::new(["Joe Biden", "Donald Trump", "Barack Obama"], 3)
+        .expect("Failed to create matcher");
+
+    let idx = matcher.insert("Angela Merkel");
+    assert_eq!(idx, 3);
+    let result = matcher.find("merkel", 1).expect("find failed");
+    assert_eq!(result.matches[0].haystack, "Angela Merkel");
+    assert_eq!(result.matches[0].haystack_idx, 3);
+
+    assert!(matcher.remove(1));
+    assert!(!matcher.remove(1));
+    assert_eq!(matcher.get(1), None);
+    assert_eq!(matcher.len(), 3);
+    let result = matcher.find("donald trump", 4).expect("find failed");
+    assert!(result.matches.iter().all(|m| m.haystack_idx != 1));
+    let result = matcher.find("barack obama", 1).expect("find failed");
+    assert_eq!(result.matches[0].haystack_idx, 2);
+
+    // Tombstones survive a save/load round trip.
+    let loaded = TFIDFMatcher::load(saved_bytes(&matcher).as_slice()).expect("load failed");
+    assert_eq!(loaded.get(1), None);
+    assert_eq!(loaded.get(3), Some("Angela Merkel"));
+}
+
+#[test]
+fn test_compact_matches_fresh_build() {
+    let mut matcher = TFIDFMatcher::new(["Joe Biden", "Donald Trump", "Barack Obama"], 3)
+        .expect("Failed to create matcher");
+    matcher.insert("Angela Merkel");
+    matcher.remove(0);
+
+    assert_eq!(matcher.compact(), vec![1, 2, 3]);
+    let fresh = TFIDFMatcher::new(["Donald Trump", "Barack Obama", "Angela Merkel"], 3)
+        .expect("Failed to create matcher");
+    assert_eq!(saved_bytes(&matcher), saved_bytes(&fresh));
+}
+
+#[test]
+fn test_builder_rejects_invalid_config() {
+    assert!(matches!(
+        TFIDFMatcher::new(["test"], 0),
+        Err(MatcherError::InvalidConfig(_))
+    ));
+    assert!(matches!(
+        TFIDFMatcher::builder()
+            .confidence_decimals(Some(16))
+            .build(["test"]),
+        Err(MatcherError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn test_builder_analysis_options() {
+    let analyze =
+        |builder: TFIDFMatcherBuilder, text: &str| builder.analyzer().analyze(text).join(" ");
+    let bigrams = TFIDFMatcher::builder().ngram_length(2);
+
+    assert_eq!(
+        analyze(bigrams.clone().case_sensitive(true), "Ab"),
+        "_A Ab b_"
+    );
+    assert_eq!(
+        analyze(bigrams.clone().boundary(Boundary::None), "ab cd"),
+        "ab cd"
+    );
+    let spanning = bigrams.clone().boundary(Boundary::None).span_words(true);
+    assert_eq!(
+        spanning.analyzer().analyze("ab cd"),
+        ["ab", "b ", " c", "cd"]
+    );
+    assert_eq!(
+        analyze(
+            TFIDFMatcher::builder().boundary(Boundary::Marker('#')),
+            "ab cd"
+        ),
+        "#ab ab# #cd cd#"
+    );
+    assert_eq!(
+        analyze(TFIDFMatcher::builder().span_words(true), "ab cd"),
+        "_ab ab_ b_c _cd cd_"
+    );
+    // Compatibility normalization folds the ligature in each n-gram; NFC keeps it.
+    assert_eq!(analyze(bigrams.clone(), "ﬁ"), "_fi fi_");
+    assert_eq!(
+        analyze(bigrams.normalization(NormalizationForm::Nfc), "ﬁ"),
+        "_ﬁ ﬁ_"
+    );
+}
+
+#[test]
+fn test_builder_config_survives_save_load() {
+    let matcher = TFIDFMatcher::builder()
+        .ngram_length(2)
+        .case_sensitive(true)
+        .boundary(Boundary::Marker('#'))
+        .idf(IdfMethod::NonSmooth)
+        .confidence_decimals(None)
+        .build(["Rust", "rust", "Trust", "Rusty"])
+        .expect("Failed to create matcher");
+    let result = matcher.find("Rust", 3).expect("find failed");
+    assert_eq!(result.matches[0].haystack_idx, 0);
+    // Unrounded confidences keep more than two decimals.
+    assert!(
+        result
+            .matches
+            .iter()
+            .any(|m| (m.confidence * 100.0).fract().abs() > 1e-9)
+    );
+
+    let loaded = TFIDFMatcher::load(saved_bytes(&matcher).as_slice()).expect("load failed");
+    assert_eq!(loaded.config(), matcher.config());
+    let reloaded = loaded.find("Rust", 3).expect("find failed");
+    for (a, b) in result.matches.iter().zip(&reloaded.matches) {
+        assert_eq!(a.haystack_idx, b.haystack_idx);
+        assert_eq!(a.confidence.to_bits(), b.confidence.to_bits());
+    }
+}
+
+#[test]
+fn test_custom_normalizer_and_tokenizer() {
+    // Whole-word tokens, with dashes folded away so SKUs match however they are punctuated.
+    let words = |text: &str, tokens: &mut Vec<String>| {
+        tokens.extend(text.split_whitespace().map(str::to_owned));
+    };
+    let strip_dashes = |text: &str| text.replace('-', "").to_uppercase();
+    let matcher = TFIDFMatcher::builder()
+        .normalizer(strip_dashes)
+        .tokenizer(words)
+        .build(["AB-100 red", "AB-200 red", "CD-100 blue"])
+        .expect("Failed to create matcher");
+    assert!(matcher.analyzer().is_custom());
+    assert_eq!(matcher.analyzer().analyze("ab-100 Red"), ["AB100", "RED"]);
+    let result = matcher.find("ab100", 3).expect("find failed");
+    assert_eq!(result.matches.len(), 1);
+    assert_eq!(result.matches[0].haystack_idx, 0);
+
+    // The custom pipeline isn't persisted, so it has to be supplied again.
+    let bytes = saved_bytes(&matcher);
+    assert!(matches!(
+        TFIDFMatcher::load(bytes.as_slice()),
+        Err(MatcherError::InvalidConfig(_))
+    ));
+    let loaded = TFIDFMatcher::load_with(bytes.as_slice(), Analyzer::new(strip_dashes, words))
+        .expect("load failed");
+    let reloaded = loaded.find("ab100", 3).expect("find failed");
+    assert_eq!(reloaded.matches[0].haystack_idx, 0);
+    assert_eq!(
+        reloaded.matches[0].confidence.to_bits(),
+        result.matches[0].confidence.to_bits()
+    );
+}
+
+#[test]
+fn test_ngram_range_and_weights() {
+    let builder = TFIDFMatcher::builder().ngram_range(2..=3);
+    assert_eq!(
+        builder.analyzer().analyze("ab"),
+        ["_a", "ab", "b_", "_ab", "ab_"]
+    );
+    let weighted = builder.clone().ngram_weights([0.5, 2.0]);
+    assert!((weighted.analyzer().weight("ab") - 0.5).abs() < 1e-12);
+    assert!((weighted.analyzer().weight("_ab") - 2.0).abs() < 1e-12);
+
+    let haystack = ["Xi Jinping", "Narendra Modi", "Jair Bolsonaro"];
+    let trigrams = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let mixed = builder.build(haystack).expect("Failed to create matcher");
+    let mixed_score = mixed.find("Jinping Xi", 1).expect("find failed").matches[0].confidence;
+    let trigram_score = trigrams.find("Jinping Xi", 1).expect("find failed").matches[0].confidence;
+    assert!(mixed_score >= trigram_score);
+
+    for invalid in [
+        TFIDFMatcher::builder().ngram_range(0..=2),
+        TFIDFMatcher::builder()
+            .ngram_range(2..=4)
+            .ngram_weights([1.0]),
+        TFIDFMatcher::builder()
+            .ngram_range(2..=3)
+            .ngram_weights([1.0, -1.0]),
+    ] {
+        assert!(matches!(
+            invalid.build(haystack),
+            Err(MatcherError::InvalidConfig(_))
+        ));
+    }
+}
+
+#[test]
+fn test_word_and_hybrid_tokenization() {
+    let words = TFIDFMatcher::builder().tokenization(Tokenization::Words);
+    assert_eq!(
+        words.analyzer().analyze("Apple iPhone-15 Pro"),
+        ["apple", "iphone", "15", "pro"]
+    );
+    let hybrid = TFIDFMatcher::builder()
+        .tokenization(Tokenization::Hybrid)
+        .ngram_length(2)
+        .word_weight(3.0);
+    let tokens = hybrid.analyzer().analyze("ab");
+    assert_eq!(tokens, ["_a", "ab", "b_", "\u{1f}ab"]);
+    assert!((hybrid.analyzer().weight(&tokens[3]) - 3.0).abs() < 1e-12);
+    assert!((hybrid.analyzer().weight(&tokens[1]) - 1.0).abs() < 1e-12);
+
+    // Weighting words lifts a title that shares the rare brand name but few n-grams.
+    let haystack = [
+        "Bosch wireless noise cancelling headphones",
+        "Bose wireless headset",
+        "Boss noise cancelling headphones",
+    ];
+    let needle = "bose noise cancelling headphones";
+    let score = |matcher: &TFIDFMatcher| {
+        let result = matcher.find(needle, 3).expect("find failed");
+        let bose = result.matches.iter().find(|m| m.haystack_idx == 1);
+        bose.expect("brand match missing").confidence
+    };
+    let chars = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let hybrid = TFIDFMatcher::builder()
+        .tokenization(Tokenization::Hybrid)
+        .word_weight(4.0)
+        .build(haystack)
+        .expect("Failed to create matcher");
+    assert!(score(&hybrid) > score(&chars));
+
+    assert!(matches!(
+        TFIDFMatcher::builder().word_weight(0.0).build(haystack),
+        Err(MatcherError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn test_tf_and_idf_methods() {
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+    assert!(close(IdfMethod::Plain.idf(4, 2), 2f64.ln()));
+    assert!(close(IdfMethod::Plain.idf(4, 4), 0.0));
+    assert!(close(IdfMethod::Probabilistic.idf(4, 1), 3f64.ln()));
+    assert!(close(IdfMethod::Probabilistic.idf(4, 3), 0.0));
+    assert!(close(IdfMethod::Bm25.idf(4, 1), (1.0 + 3.5 / 1.5f64).ln()));
+    assert!(close(
+        TfMethod::Sublinear.tf(3.0, 10.0, 10.0),
+        1.0 + 3f64.ln()
+    ));
+    assert!(close(TfMethod::Binary.tf(3.0, 10.0, 10.0), 1.0));
+    let bm25 = TfMethod::Bm25 { k1: 1.2, b: 0.75 };
+    assert!(close(bm25.tf(1.0, 10.0, 10.0), 1.0));
+    assert!(bm25.tf(1.0, 20.0, 10.0) < bm25.tf(1.0, 5.0, 10.0));
+
+    // Length normalization favors the short entry that contains the needle over a long one.
+    let haystack = [
+        "acme",
+        "acme industrial supply company incorporated worldwide",
+        "zenith",
+    ];
+    let matcher = TFIDFMatcher::builder()
+        .tokenization(Tokenization::Words)
+        .bm25(1.2, 1.0)
+        .build(haystack)
+        .expect("Failed to create matcher");
+    let result = matcher.find("acme", 2).expect("find failed");
+    assert_eq!(result.matches[0].haystack_idx, 0);
+    assert!(result.matches[0].confidence > result.matches[1].confidence);
+
+    assert!(matches!(
+        TFIDFMatcher::builder().bm25(1.2, 2.0).build(haystack),
+        Err(MatcherError::InvalidConfig(_))
+    ));
+}
+
+#[test]
+fn test_zero_idf_features_do_not_duplicate_matches() {
+    // The n-grams of "aaa" occur in every entry, so these methods weight them 0.
+    let haystack = ["aaa x", "aaa y", "aaa z"];
+    for idf in [
+        IdfMethod::Plain,
+        IdfMethod::Probabilistic,
+        IdfMethod::Textbook,
+    ] {
+        let matcher = TFIDFMatcher::builder()
+            .idf(idf)
+            .build(haystack)
+            .expect("Failed to create matcher");
+        for similarity in [
+            Similarity::Cosine,
+            Similarity::Jaccard,
+            Similarity::Dice,
+            Similarity::Overlap,
+            Similarity::Containment,
+        ] {
+            let options = FindOptions::new().similarity(similarity);
+            let result = matcher
+                .find_with("aaa x", 10, &options)
+                .expect("find failed");
+            let mut seen: Vec<usize> = result.matches.iter().map(|m| m.haystack_idx).collect();
+            assert_eq!(seen.len(), haystack.len(), "{idf:?} {similarity:?}");
+            seen.sort_unstable();
+            seen.dedup();
+            assert_eq!(seen.len(), haystack.len(), "{idf:?} {similarity:?}");
+            assert_eq!(result.matches[0].haystack_idx, 0);
+        }
+        let above = matcher.find_above("aaa x", 0.0).expect("find_above failed");
+        assert_eq!(above.matches.len(), haystack.len(), "{idf:?}");
+    }
+}
+
+#[test]
+fn test_similarity_measures() {
+    let matcher = TFIDFMatcher::new(["acme corporation", "acme", "zenith"], 3)
+        .expect("Failed to create matcher");
+    let score = |needle: &str, idx: usize, similarity: Similarity| {
+        let options = FindOptions::new().similarity(similarity);
+        let result = matcher.find_with(needle, 3, &options).expect("find failed");
+        result
+            .matches
+            .iter()
+            .find(|m| m.haystack_idx == idx)
+            .map_or(0.0, |m| m.confidence)
+    };
+
+    for similarity in [
+        Similarity::Cosine,
+        Similarity::Jaccard,
+        Similarity::Dice,
+        Similarity::Overlap,
+        Similarity::Containment,
+    ] {
+        assert!((score("acme", 1, similarity) - 1.0).abs() < 1e-9);
+    }
+    // "acme" is wholly contained in "acme corporation", but not the other way around.
+    assert!((score("acme", 0, Similarity::Containment) - 1.0).abs() < 1e-9);
+    assert!((score("acme", 0, Similarity::Overlap) - 1.0).abs() < 1e-9);
+    assert!(score("acme corporation", 1, Similarity::Containment) < 0.5);
+    let jaccard = score("acme", 0, Similarity::Jaccard);
+    let dice = score("acme", 0, Similarity::Dice);
+    assert!(0.0 < jaccard && jaccard < dice && dice < 1.0);
+
+    // The weight sums the set measures rely on survive persistence and incremental inserts.
+    let mut loaded = TFIDFMatcher::load(saved_bytes(&matcher).as_slice()).expect("load failed");
+    let options = FindOptions::new().similarity(Similarity::Dice);
+    let expected = matcher.find_with("acme", 3, &options).expect("find failed");
+    let actual = loaded.find_with("acme", 3, &options).expect("find failed");
+    for (a, b) in expected.matches.iter().zip(&actual.matches) {
+        assert_eq!(a.confidence.to_bits(), b.confidence.to_bits());
+    }
+    let idx = loaded.insert("acme");
+    let result = loaded.find_with("acme", 4, &options).expect("find failed");
+    assert!(result.matches.iter().any(|m| m.haystack_idx == idx));
+}
+
+#[test]
+fn test_set_similarities_count_unknown_needle_words() {
+    let matcher = TFIDFMatcher::new(["Acme", "Globex"], 3).expect("Failed to create matcher");
+    let score = |needle: &str, similarity: Similarity| {
+        let options = FindOptions::new().similarity(similarity);
+        let result = matcher.find_with(needle, 1, &options).expect("find failed");
+        assert_eq!(result.matches[0].haystack_idx, 0);
+        result.matches[0].score
+    };
+    for similarity in [
+        Similarity::Jaccard,
+        Similarity::Dice,
+        Similarity::Containment,
+    ] {
+        let padded = score("acme qwertyuiop zxcvbnm", similarity);
+        assert!(padded < 0.5, "{similarity:?}: {padded}");
+        assert!(
+            padded < score("acme qwertyuiop", similarity),
+            "{similarity:?}"
+        );
+    }
+    // "Acme" is still wholly contained in the needle.
+    assert!((score("acme qwertyuiop zxcvbnm", Similarity::Overlap) - 1.0).abs() < 1e-9);
+
+    // Batched and streamed queries count them the same way.
+    let needle = "acme qwertyuiop zxcvbnm";
+    let options = FindOptions::new().similarity(Similarity::Jaccard);
+    let jaccard = score(needle, Similarity::Jaccard).to_bits();
+    let many = matcher
+        .find_many_with(vec![needle], 1, &options)
+        .expect("find_many failed");
+    assert_eq!(many[0].matches[0].score.to_bits(), jaccard);
+    let streamed: Vec<_> = matcher.find_iter_with([needle], 1, &options).collect();
+    assert_eq!(streamed[0].1[0].score.to_bits(), jaccard);
+}
+
+#[test]
+fn test_min_confidence_and_find_above() {
+    let matcher = TFIDFMatcher::new(["test", "tests", "testing", "tester", "example"], 3)
+        .expect("Failed to create matcher");
+    let everything = matcher
+        .find_with("test", usize::MAX, &FindOptions::new())
+        .expect("find failed");
+    assert_eq!(everything.matches.len(), 4);
+
+    let above = matcher.find_above("test", 0.5).expect("find failed");
+    assert!(!above.matches.is_empty());
+    assert!(above.matches.len() < everything.matches.len());
+    assert!(above.matches.iter().all(|m| m.confidence >= 0.5));
+    assert!(
+        above
+            .matches
+            .windows(2)
+            .all(|pair| pair[0].confidence >= pair[1].confidence)
+    );
+
+    // The threshold combines with top_k.
+    let options = FindOptions::new().min_confidence(0.5);
+    let top = matcher.find_with("test", 1, &options).expect("find failed");
+    assert_eq!(top.matches.len(), 1);
+    assert_eq!(top.matches[0].haystack_idx, above.matches[0].haystack_idx);
+
+    let many = matcher
+        .find_many_above(["test", "example"], 0.99)
+        .expect("find_many failed");
+    assert_eq!(many[0].matches.len(), 1);
+    assert_eq!(many[1].matches[0].haystack, "example");
+}
+
+#[test]
+fn test_raw_scores() {
+    let matcher = TFIDFMatcher::builder()
+        .idf(IdfMethod::NonSmooth)
+        .build(["Rust", "rust", "Trust", "Rusty"])
+        .expect("Failed to create matcher");
+    let result = matcher.find("Rust", 4).expect("find failed");
+    for m in &result.matches {
+        assert!((m.confidence - m.score).abs() <= 0.005 + 1e-12);
+        assert_eq!(
+            m.confidence.to_bits(),
+            ((m.score * 100.0).round() / 100.0).to_bits()
+        );
+    }
+    assert!(
+        result
+            .matches
+            .iter()
+            .any(|m| (m.score * 100.0).fract().abs() > 1e-9)
+    );
+
+    let unrounded = TFIDFMatcher::builder()
+        .idf(IdfMethod::NonSmooth)
+        .confidence_decimals(None)
+        .build(["Rust", "rust", "Trust", "Rusty"])
+        .expect("Failed to create matcher");
+    let raw = unrounded.find("Rust", 4).expect("find failed");
+    for (a, b) in result.matches.iter().zip(&raw.matches) {
+        assert_eq!(a.score.to_bits(), b.score.to_bits());
+        assert_eq!(b.confidence.to_bits(), b.score.to_bits());
+    }
+}
+
+#[test]
+fn test_ties_prefer_lower_index() {
+    let haystack = ["apple", "banana", "apple", "cherry", "apple", "apple"];
+    let matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let idxs = |needle: &Needle| {
+        needle
+            .matches
+            .iter()
+            .map(|m| m.haystack_idx)
+            .collect::<Vec<_>>()
+    };
+
+    let result = matcher.find("apple", 3).expect("find failed");
+    assert_eq!(idxs(&result), [0, 2, 4]);
+    let all = matcher.find("apple", 10).expect("find failed");
+    assert_eq!(idxs(&all)[..4], [0, 2, 4, 5]);
+
+    let many = matcher
+        .find_many(["cherry", "apple"], 3)
+        .expect("find_many failed");
+    assert_eq!(idxs(&many[1]), idxs(&result));
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_matches_sequential() {
+    let haystack: Vec<String> = (0..500)
+        .map(|i| format!("entry {i} {}", ["alpha", "beta", "gamma"][i % 3]))
+        .collect();
+    let matcher = TFIDFMatcher::new(haystack.clone(), 3).expect("Failed to create matcher");
+    let par_matcher = TFIDFMatcher::par_new(haystack.clone(), 3).expect("Failed to create matcher");
+    assert_eq!(saved_bytes(&par_matcher), saved_bytes(&matcher));
+
+    let needles: Vec<String> = (0..200).map(|i| format!("entry {} beta", i * 7)).collect();
+    let needles: Vec<&str> = needles.iter().map(String::as_str).collect();
+    // V2 vectorizes each needle in its own task; V1 needs the batch IDF first.
+    let v1 = TFIDFMatcher::builder()
+        .scoring_version(ScoringVersion::V1)
+        .build(haystack)
+        .expect("Failed to create matcher");
+    for matcher in [&matcher, &v1] {
+        let sequential = matcher
+            .find_many(needles.clone(), 5)
+            .expect("find_many failed");
+        let parallel = matcher
+            .par_find_many(needles.clone(), 5)
+            .expect("par_find_many failed");
+        assert_eq!(parallel.len(), sequential.len());
+        for (a, b) in sequential.iter().zip(&parallel) {
+            assert_eq!(a.needle, b.needle);
+            assert_eq!(a.matches.len(), b.matches.len());
+            for (x, y) in a.matches.iter().zip(&b.matches) {
+                assert_eq!(x.haystack_idx, y.haystack_idx);
+                assert_eq!(x.score.to_bits(), y.score.to_bits());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_build_is_byte_identical() {
+    let haystack: Vec<String> = (0..2_000)
+        .map(|i| {
+            format!(
+                "{} street {} apt {}",
+                ["Oak", "Elm", "Main", "Ash"][i % 4],
+                i,
+                i % 17
+            )
+        })
+        .collect();
+    let builders = [
+        TFIDFMatcher::builder(),
+        TFIDFMatcher::builder()
+            .ngram_range(2..=4)
+            .ngram_weights([0.5, 1.0, 2.0]),
+        TFIDFMatcher::builder()
+            .tokenization(Tokenization::Hybrid)
+            .bm25(1.2, 0.75),
+        TFIDFMatcher::builder()
+            .tf(TfMethod::Sublinear)
+            .idf(IdfMethod::Probabilistic),
+    ];
+    for builder in builders {
+        let sequential = builder.build(haystack.clone()).expect("build failed");
+        // Postings are transposed in one shard per thread; uneven shard counts included.
+        for threads in [1, 3, 8] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .expect("thread pool failed");
+            let parallel = pool
+                .install(|| builder.par_build(haystack.clone()))
+                .expect("build failed");
+            assert_eq!(saved_bytes(&parallel), saved_bytes(&sequential));
+        }
+    }
+
+    let empty = TFIDFMatcher::builder()
+        .par_build(Vec::<String>::new())
+        .expect("build failed");
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn test_find_iter_streams_in_chunks() {
+    let matcher = TFIDFMatcher::new(["Apple Inc", "Microsoft", "Google", "Amazon"], 3)
+        .expect("Failed to create matcher");
+    let needles = ["apple", "micro soft", "gogle", "amazon.com", "xyz"];
+    let batch = matcher.find_many(needles, 2).expect("find_many failed");
+
+    let drawn = std::cell::Cell::new(0);
+    let source = needles.iter().map(|&needle| {
+        drawn.set(drawn.get() + 1);
+        needle.to_owned()
+    });
+    let mut stream = matcher.find_iter(source, 2).chunk_size(2);
+    let (first, _) = stream.next().expect("stream ended early");
+    assert_eq!(first, "apple");
+    assert_eq!(drawn.get(), 2);
+
+    let streamed: Vec<(String, Vec<MatchEntry>)> =
+        std::iter::once((first, batch[0].matches.clone()))
+            .chain(stream)
+            .collect();
+    assert_eq!(drawn.get(), needles.len());
+    assert_eq!(streamed.len(), batch.len());
+    for ((needle, matches), expected) in streamed.iter().zip(&batch) {
+        assert_eq!(needle, expected.needle);
+        let idxs: Vec<usize> = matches.iter().map(|m| m.haystack_idx).collect();
+        let expected_idxs: Vec<usize> = expected.matches.iter().map(|m| m.haystack_idx).collect();
+        assert_eq!(idxs, expected_idxs);
+        for (m, e) in matches.iter().zip(&expected.matches) {
+            assert_eq!(m.score.to_bits(), e.score.to_bits());
+        }
+    }
+}
+
+#[test]
+fn test_owned_results_and_shared_matcher() {
+    let matcher = TFIDFMatcher::new(["Apple Inc", "Microsoft", "Google"], 3)
+        .expect("Failed to create matcher");
+    let borrowed = matcher.find("apple", 2).expect("find failed");
+    let owned = {
+        let needle = String::from("apple");
+        matcher.find(&needle, 2).expect("find failed").into_owned()
+    };
+    assert_eq!(owned.needle, "apple");
+    assert_eq!(owned.matches.len(), borrowed.matches.len());
+    assert_eq!(owned.matches[0], borrowed.matches[0].clone().into_owned());
+
+    let shared = SharedMatcher::new(matcher);
+    let handles: Vec<_> = ["apple", "micro soft"]
+        .into_iter()
+        .map(|needle| {
+            let shared = shared.clone();
+            std::thread::spawn(move || shared.find(needle, 1).expect("find failed"))
+        })
+        .collect();
+    let results: Vec<OwnedNeedle> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+    assert_eq!(results[0].matches[0].haystack, "Apple Inc");
+    assert_eq!(results[1].matches[0].haystack, "Microsoft");
+
+    let many = shared.find_many(["google"], 1).expect("find_many failed");
+    drop(shared);
+    assert_eq!(many[0].matches[0].haystack_idx, 2);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_results_and_config() {
+    let config = TFIDFMatcher::builder()
+        .ngram_range(2..=3)
+        .boundary(Boundary::Marker('#'))
+        .bm25(1.5, 0.5)
+        .config()
+        .clone();
+    let json = serde_json::to_string(&config).expect("serialize failed");
+    let parsed: MatcherConfig = serde_json::from_str(&json).expect("deserialize failed");
+    assert_eq!(parsed, config);
+
+    // Omitted settings take their defaults.
+    let partial: MatcherConfig =
+        serde_json::from_str(r#"{"case_sensitive": true}"#).expect("deserialize failed");
+    assert_eq!(
+        partial,
+        MatcherConfig {
+            case_sensitive: true,
+            ..MatcherConfig::default()
+        }
+    );
+    let options: FindOptions =
+        serde_json::from_str(r#"{"min_confidence": 0.5}"#).expect("deserialize failed");
+    assert_eq!(options, FindOptions::new().min_confidence(0.5));
+
+    let matcher = TFIDFMatcherBuilder::from_config(parsed)
+        .build(["Apple Inc", "Microsoft"])
+        .expect("Failed to create matcher");
+    let result = matcher.find("apple", 1).expect("find failed");
+    let borrowed = serde_json::to_value(&result).expect("serialize failed");
+    let owned = result.into_owned();
+    assert_eq!(
+        borrowed,
+        serde_json::to_value(&owned).expect("serialize failed")
+    );
+    let round_trip: OwnedNeedle = serde_json::from_value(borrowed).expect("deserialize failed");
+    assert_eq!(round_trip, owned);
+}
+
+#[test]
+fn test_keyed_matcher_payloads() {
+    #[derive(Debug, PartialEq)]
+    struct Record {
+        id: u64,
+        name: &'static str,
+    }
+    let records = vec![
+        Record {
+            id: 10,
+            name: "Apple Inc",
+        },
+        Record {
+            id: 20,
+            name: "Microsoft",
+        },
+        Record {
+            id: 30,
+            name: "Google",
+        },
+    ];
+    let mut matcher =
+        TFIDFMatcher::new_with_keys(records, 3, |r| r.name).expect("Failed to create matcher");
+    assert_eq!(matcher.len(), 3);
+    let result = matcher.find("micro soft", 1).expect("find failed");
+    assert_eq!(result.matches[0].payload.id, 20);
+    assert_eq!(result.matches[0].entry.haystack, "Microsoft");
+
+    let amazon = matcher.insert(
+        "Amazon",
+        Record {
+            id: 40,
+            name: "Amazon",
+        },
+    );
+    assert_eq!(
+        matcher.get(amazon).map(|(text, r)| (text, r.id)),
+        Some(("Amazon", 40))
+    );
+    assert_eq!(matcher.remove(0).map(|r| r.id), Some(10));
+    assert_eq!(matcher.remove(0), None);
+    assert!(matcher.payload(0).is_none());
+
+    // Payloads follow their entries when compaction renumbers them.
+    assert_eq!(matcher.compact(), [1, 2, 3]);
+    let many = matcher
+        .find_many(["google", "amazon"], 1)
+        .expect("find_many failed");
+    assert_eq!(many[0].matches[0].payload.id, 30);
+    assert_eq!(many[0].matches[0].entry.haystack_idx, 1);
+    assert_eq!(many[1].matches[0].payload.id, 40);
+    assert_eq!(matcher.payload(2).map(|r| r.name), Some("Amazon"));
+}
+
+#[test]
+fn test_filters_restrict_candidates() {
+    let haystack = ["Acme Berlin", "Acme Paris", "Acme Munich", "Acme London"];
+    let matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let idxs = |needle: &Needle| {
+        needle
+            .matches
+            .iter()
+            .map(|m| m.haystack_idx)
+            .collect::<Vec<_>>()
+    };
+
+    let german: DocSet = [0, 2].into_iter().collect();
+    assert_eq!(german.len(), 2);
+    let allow = FindOptions::new().filter(Filter::allow(german.clone()));
+    // Filtered-out entries never take a top-k slot: the best German match is found even though
+    // the needle is closer to entries outside the filter.
+    let result = matcher
+        .find_with("acme paris", 1, &allow)
+        .expect("find failed");
+    assert_eq!(idxs(&result), [0]);
+    let all = matcher
+        .find_with("acme", usize::MAX, &allow)
+        .expect("find failed");
+    assert_eq!(idxs(&all), [0, 2]);
+
+    let deny = FindOptions::new().filter(Filter::deny(german));
+    let result = matcher.find_with("acme", 10, &deny).expect("find failed");
+    assert_eq!(idxs(&result), [1, 3]);
+
+    let odd = FindOptions::new().filter(Filter::predicate(|idx| idx % 2 == 1));
+    let many = matcher
+        .find_many_with(["acme munich", "acme london"], 1, &odd)
+        .expect("find_many failed");
+    assert_eq!(idxs(&many[0]), [1]);
+    assert_eq!(idxs(&many[1]), [3]);
+}
+
+#[test]
+fn test_attribute_filters_follow_updates_and_persist() {
+    // Over 64 entries, so tag bitsets span several words.
+    let haystack: Vec<String> = (0..70).map(|i| format!("Acme Supplier {i}")).collect();
+    let mut matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    for idx in [3, 10, 66] {
+        assert!(matcher.tag(idx, "country:DE"));
+    }
+    assert!(!matcher.tag(3, "country:DE"));
+    assert!(matcher.tag(10, "active"));
+    assert!(matcher.tag(66, "active"));
+    assert!(matcher.set_number(3, "revenue", 5.0));
+    assert!(matcher.set_number(66, "revenue", 2.0));
+    assert!(!matcher.set_number(70, "revenue", 1.0));
+
+    let idxs = |matcher: &TFIDFMatcher, filter: Filter| {
+        let options = FindOptions::new().filter(filter);
+        let result = matcher
+            .find_with("acme supplier", usize::MAX, &options)
+            .expect("find failed");
+        let mut idxs: Vec<usize> = result.matches.iter().map(|m| m.haystack_idx).collect();
+        idxs.sort_unstable();
+        idxs
+    };
+    let de = || Filter::tag("country:DE");
+    assert_eq!(idxs(&matcher, de()), [3, 10, 66]);
+    assert_eq!(idxs(&matcher, de() & Filter::tag("active")), [10, 66]);
+    assert_eq!(idxs(&matcher, Filter::range("revenue", 1.0, 3.0)), [66]);
+    assert_eq!(
+        idxs(
+            &matcher,
+            de() & !Filter::range("revenue", 0.0, f64::INFINITY)
+        ),
+        [10]
+    );
+    assert_eq!(
+        idxs(&matcher, Filter::tag("missing") | Filter::tag("active")),
+        [10, 66]
+    );
+
+    // The filter is resolved per query, so it covers inserted entries and forgets removed ones.
+    let inserted = matcher.insert("Acme Supplier new");
+    assert!(matcher.tag(inserted, "country:DE"));
+    assert!(matcher.remove(10));
+    assert!(!matcher.has_tag(10, "country:DE"));
+    assert_eq!(idxs(&matcher, de()), [3, 66, inserted]);
+    assert_eq!(matcher.unset_number(3, "revenue"), Some(5.0));
+    assert_eq!(matcher.number(3, "revenue"), None);
+    assert!(matcher.untag(3, "country:DE"));
+
+    // Attributes survive saving, in memory and mapped.
+    let bytes = saved_bytes(&matcher);
+    let loaded = TFIDFMatcher::load(bytes.as_slice()).expect("load failed");
+    assert_eq!(idxs(&loaded, de()), [66, inserted]);
+    assert_eq!(loaded.number(66, "revenue"), Some(2.0));
+    assert_eq!(saved_bytes(&loaded), bytes);
+    #[cfg(feature = "mmap")]
+    {
+        let path =
+            std::env::temp_dir().join(format!("tf-idf-matcher-attrs-{}.idx", std::process::id()));
+        matcher.save_to_path(&path).expect("save failed");
+        // SAFETY: the file is private to this test and not modified while mapped.
+        let mapped = unsafe { MappedMatcher::open(&path) }.expect("open failed");
+        mapped.verify().expect("verify failed");
+        let options = FindOptions::new().filter(de() & Filter::range("revenue", 2.0, 2.0));
+        let result = mapped
+            .find_with("acme supplier", usize::MAX, &options)
+            .expect("find failed");
+        assert_eq!(result.matches.len(), 1);
+        assert_eq!(result.matches[0].haystack_idx, 66);
+        drop(mapped);
+        std::fs::remove_file(&path).expect("cleanup failed");
+    }
+
+    // Compaction renumbers the entries, and their attributes with them.
+    let kept = matcher.compact();
+    let new_idx = |old| kept.iter().position(|&idx| idx == old).expect("entry kept");
+    assert_eq!(idxs(&matcher, de()), [new_idx(66), new_idx(inserted)]);
+    assert!(matcher.has_tag(new_idx(66), "active"));
+    assert_eq!(matcher.number(new_idx(66), "revenue"), Some(2.0));
+}
+
+#[test]
+fn test_self_join_pairs() {
+    let haystack = [
+        "Acme Corporation",
+        "Globex",
+        "ACME Corporation",
+        "Initech",
+        "Acme Corporation Ltd",
+        "Globex Inc",
+    ];
+    let mut matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let pairs = matcher.self_join(0.5, 10);
+    let keys: Vec<(usize, usize)> = pairs.iter().map(|&(i, j, _)| (i, j)).collect();
+    assert_eq!(keys, [(0, 2), (0, 4), (1, 5), (2, 4)]);
+
+    // Scores agree with querying either entry of the pair by its text.
+    for &(i, j, score) in &pairs {
+        assert!(score >= 0.5);
+        let result = matcher.find(haystack[i], 10).expect("find failed");
+        let found = result.matches.iter().find(|m| m.haystack_idx == j).unwrap();
+        assert!((found.score - score).abs() < 1e-12);
+    }
+
+    // top_k limits each entry's partners; a pair survives if either side keeps it.
+    let top1 = matcher.self_join(0.5, 1);
+    assert!(top1.iter().all(|&(i, j, _)| i < j));
+    assert!(top1.len() < pairs.len());
+    assert!(top1.iter().any(|&(i, j, _)| (i, j) == (1, 5)));
+
+    assert!(matcher.remove(0));
+    let keys: Vec<(usize, usize)> = matcher
+        .self_join(0.5, 10)
+        .iter()
+        .map(|&(i, j, _)| (i, j))
+        .collect();
+    assert_eq!(keys, [(1, 5), (2, 4)]);
+
+    // The "aaa" n-grams occur everywhere and weigh 0 under plain IDF, so entries sharing only
+    // them score 0 and are not paired even at threshold 0.
+    let matcher = TFIDFMatcher::builder()
+        .idf(IdfMethod::Plain)
+        .build(["aaa xyz", "aaa y", "aaa z", "aaa xyzw"])
+        .expect("Failed to create matcher");
+    let pairs = matcher.self_join(0.0, 10);
+    assert_eq!(pairs.len(), 1);
+    assert_eq!((pairs[0].0, pairs[0].1), (0, 3));
+    assert!(pairs[0].2 > 0.0);
+}
+
+#[test]
+fn test_clustering_linkages_and_canonical() {
+    // A chain: 0 ~ 1 and 1 ~ 2, but 0 and 2 are far apart.
+    let haystack = [
+        "Northwind Traders",
+        "Northwind Traders Intl",
+        "Intl Traders",
+        "Contoso",
+        "Contoso Ltd",
+    ];
+    let matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let pairs = matcher.self_join(0.0, 10);
+    let score = |i: usize, j: usize| {
+        pairs
+            .iter()
+            .find(|p| (p.0, p.1) == (i, j))
+            .map_or(0.0, |p| p.2)
+    };
+    let threshold = score(0, 1).min(score(1, 2)).min(score(3, 4)) - 1e-9;
+    assert!(score(0, 2) < threshold);
+
+    let single = matcher.cluster(threshold, &ClusterOptions::new());
+    assert_eq!(
+        single.assignments,
+        [Some(0), Some(0), Some(0), Some(1), Some(1)]
+    );
+    assert_eq!(single.clusters[0].members, [0, 1, 2]);
+    // The middle of the chain is the most central member.
+    assert_eq!(single.clusters[0].canonical, 1);
+
+    for linkage in [Linkage::Complete, Linkage::Average] {
+        let strict = matcher.cluster(threshold, &ClusterOptions::new().linkage(linkage));
+        assert_eq!(strict.clusters.len(), 3, "{linkage:?}");
+        assert_eq!(strict.assignments[3], strict.assignments[4]);
+        assert_ne!(strict.assignments[0], strict.assignments[2]);
+    }
+
+    let longest = matcher.cluster(
+        threshold,
+        &ClusterOptions::new().canonical(Canonical::Longest),
+    );
+    assert_eq!(longest.clusters[1].canonical, 4);
+    let first = matcher.cluster(
+        threshold,
+        &ClusterOptions::new().canonical(Canonical::custom(|members| members[0])),
+    );
+    assert_eq!(first.clusters[0].canonical, 0);
+
+    let mut matcher = matcher;
+    assert!(matcher.remove(1));
+    let after = matcher.cluster(threshold, &ClusterOptions::new());
+    assert_eq!(after.assignments[1], None);
+    assert_ne!(after.assignments[0], after.assignments[2]);
+
+    // At threshold 0, entries sharing only zero-weight features still stay apart.
+    let matcher = TFIDFMatcher::builder()
+        .idf(IdfMethod::Plain)
+        .build(["aaa xyz", "aaa y", "aaa z", "aaa xyzw"])
+        .expect("Failed to create matcher");
+    for linkage in [Linkage::Single, Linkage::Complete, Linkage::Average] {
+        let clustering = matcher.cluster(0.0, &ClusterOptions::new().linkage(linkage));
+        assert_eq!(
+            clustering.assignments,
+            [Some(0), Some(1), Some(2), Some(0)],
+            "{linkage:?}"
+        );
+    }
+}
+
+#[test]
+fn test_link_greedy_and_optimal() {
+    let right = ["Acme Holdings", "Acme Corporation", "Globex", "Initech"];
+    let left = ["Acme Holdings Corp", "Holdings", "Umbrella"];
+    let matcher = TFIDFMatcher::new(right, 3).unwrap();
+    let pairs = |links: &Links| -> Vec<(usize, usize)> {
+        links.pairs.iter().map(|&(l, r, _)| (l, r)).collect()
+    };
+
+    // Greedy takes the single strongest pair, leaving "Holdings" without a partner.
+    let greedy = matcher.link(left, 0.3, &LinkOptions::new()).unwrap();
+    assert_eq!(pairs(&greedy), [(0, 0)]);
+    assert_eq!(greedy.unmatched_left, [1, 2]);
+    assert_eq!(greedy.unmatched_right, [1, 2, 3]);
+
+    // The optimal assignment gives up that pair for a higher total.
+    let options = LinkOptions::new().assignment(Assignment::Optimal);
+    let optimal = matcher.link(left, 0.3, &options).unwrap();
+    assert_eq!(pairs(&optimal), [(0, 1), (1, 0)]);
+    assert_eq!(optimal.unmatched_left, [2]);
+    assert_eq!(optimal.unmatched_right, [2, 3]);
+    let total = |links: &Links| links.pairs.iter().map(|pair| pair.2).sum::<f64>();
+    assert!(total(&optimal) > total(&greedy));
+
+    // Building over the right side gives the same links; removed entries are never unmatched.
+    let built = TFIDFMatcher::builder()
+        .link(left, right, 0.3, &options)
+        .unwrap();
+    assert_eq!(built, optimal);
+    let mut matcher = matcher;
+    assert!(matcher.remove(3));
+    let after = matcher.link(left, 0.3, &options).unwrap();
+    assert_eq!(after.unmatched_right, [2]);
+}
+
+#[test]
+fn test_optimal_link_matches_brute_force() {
+    /// The best total over one-to-one subsets of `candidates[l]`, `(right, score)` per left record.
+    fn best_total(candidates: &[Vec<(usize, f64)>], taken: &mut Vec<usize>) -> f64 {
+        let Some((row, rest)) = candidates.split_first() else {
+            return 0.0;
+        };
+        let mut best = best_total(rest, taken);
+        for &(r, score) in row {
+            if !taken.contains(&r) {
+                taken.push(r);
+                best = best.max(score + best_total(rest, taken));
+                taken.pop();
+            }
+        }
+        best
+    }
+
+    let words = ["ab", "bc", "ca", "abc", "bca", "cab"];
+    let mut state = 0x2545_f491_u64;
+    let mut next_word = || {
+        state = state
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1);
+        words[usize::try_from(state >> 61).unwrap() % words.len()]
+    };
+    let mut record = || format!("{} {}", next_word(), next_word());
+    for trial in 0..40 {
+        // Dense candidate graphs, then sparse ones leaving records unmatched.
+        let threshold = [0.2, 0.6][trial % 2];
+        let right: Vec<String> = (0..6).map(|_| record()).collect();
+        let left: Vec<String> = (0..6).map(|_| record()).collect();
+        let left: Vec<&str> = left.iter().map(String::as_str).collect();
+        let matcher = TFIDFMatcher::new(right, 2).unwrap();
+        let options = FindOptions::new().min_confidence(threshold);
+        let candidates: Vec<Vec<(usize, f64)>> = matcher
+            .find_many_with(left.clone(), 6, &options)
+            .unwrap()
+            .into_iter()
+            .map(|needle| {
+                let entries = needle.matches.iter();
+                entries.map(|m| (m.haystack_idx, m.score)).collect()
+            })
+            .collect();
+
+        let options = LinkOptions::new().assignment(Assignment::Optimal).top_k(6);
+        let links = matcher.link(left.clone(), threshold, &options).unwrap();
+        let total: f64 = links.pairs.iter().map(|pair| pair.2).sum();
+        assert!((total - best_total(&candidates, &mut Vec::new())).abs() < 1e-9);
+        let greedy = matcher.link(
+            left,
+            threshold,
+            &options.clone().assignment(Assignment::Greedy),
+        );
+        let greedy_total: f64 = greedy.unwrap().pairs.iter().map(|pair| pair.2).sum();
+        assert!(greedy_total <= total + 1e-9);
+
+        let mut rights: Vec<usize> = links.pairs.iter().map(|pair| pair.1).collect();
+        rights.sort_unstable();
+        rights.dedup();
+        assert_eq!(rights.len(), links.pairs.len());
+        assert_eq!(links.pairs.len() + links.unmatched_left.len(), 6);
+        assert_eq!(links.pairs.len() + links.unmatched_right.len(), 6);
+    }
+}
+
+#[test]
+fn test_similarity_matrix_and_matrix_market() {
+    let haystack = ["Acme Corp", "ACME Corporation", "Globex", "Initech", "Acme"];
+    let needles = ["acme corp", "globex inc", "umbrella"];
+    let matcher = TFIDFMatcher::new(haystack, 3).unwrap();
+    let matrix = matcher.similarity_matrix(needles, 2, 0.2).unwrap();
+    assert_eq!(matrix.shape(), (needles.len(), haystack.len()));
+    assert!(matrix.is_csr());
+
+    // Each row holds exactly the thresholded top-k matches, columns ascending.
+    let options = FindOptions::new().min_confidence(0.2);
+    for (i, needle) in matcher
+        .find_many_with(needles, 2, &options)
+        .unwrap()
+        .into_iter()
+        .enumerate()
+    {
+        let row = matrix.outer_view(i).unwrap();
+        assert!(row.indices().windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(row.nnz(), needle.matches.len());
+        for entry in &needle.matches {
+            assert_eq!(
+                row.get(entry.haystack_idx).map(|score| score.to_bits()),
+                Some(entry.score.to_bits())
+            );
+        }
+    }
+    assert_eq!(matrix.outer_view(2).unwrap().nnz(), 0);
+
+    let mut out = Vec::new();
+    matcher
+        .save_similarity_matrix(needles, 2, 0.2, &mut out)
+        .unwrap();
+    let text = String::from_utf8(out).unwrap();
+    let mut lines = text.lines().filter(|line| !line.starts_with('%'));
+    assert_eq!(lines.next(), Some(format!("3 5 {}", matrix.nnz()).as_str()));
+    assert_eq!(lines.count(), matrix.nnz());
+    assert!(text.starts_with("%%MatrixMarket matrix coordinate real general"));
+}
+
+#[test]
+fn test_similarity_matrix_with_zero_idf_features() {
+    let haystack = ["aaa x", "aaa y", "aaa z"];
+    let matcher = TFIDFMatcher::builder()
+        .idf(IdfMethod::Probabilistic)
+        .build(haystack)
+        .expect("Failed to create matcher");
+    let matrix = matcher
+        .similarity_matrix(["aaa x"], 5, 0.0)
+        .expect("similarity_matrix failed");
+    assert_eq!(matrix.outer_view(0).unwrap().indices(), [0, 1, 2]);
+}
+
+#[test]
+fn test_knn_graph_edges_and_export() {
+    let haystack = [
+        "Acme Corp",
+        "ACME Corp.",
+        "Acme Corporation",
+        "Globex",
+        "Globex Inc",
+    ];
+    let mut matcher = TFIDFMatcher::new(haystack, 3).unwrap();
+    let edges = matcher.knn_graph(2, 0.3);
+    for source in 0..haystack.len() {
+        let out: Vec<_> = edges.iter().filter(|edge| edge.0 == source).collect();
+        assert!(!out.is_empty() && out.len() <= 2, "{source}: {out:?}");
+        assert!(out.iter().all(|edge| edge.1 != source && edge.2 >= 0.3));
+        assert!(out.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+    }
+    assert!(edges.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    // The self-join reports exactly the edges, undirected and deduplicated.
+    for threshold in [0.3, 0.0] {
+        let mut undirected: Vec<(usize, usize)> = matcher
+            .knn_graph(2, threshold)
+            .iter()
+            .map(|&(a, b, _)| (a.min(b), a.max(b)))
+            .collect();
+        undirected.sort_unstable();
+        undirected.dedup();
+        let pairs: Vec<(usize, usize)> = matcher
+            .self_join(threshold, 2)
+            .iter()
+            .map(|&(i, j, _)| (i, j))
+            .collect();
+        assert_eq!(pairs, undirected, "{threshold}");
+    }
+
+    let mut csv = Vec::new();
+    let written = matcher
+        .write_knn_graph(2, 0.3, EdgeFormat::Csv, &mut csv)
+        .unwrap();
+    assert_eq!(written, edges.len());
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("source,target,score"));
+    for (line, &(source, target, score)) in lines.zip(&edges) {
+        assert_eq!(line, format!("{source},{target},{score}"));
+    }
+
+    let mut jsonl = Vec::new();
+    matcher
+        .write_knn_graph(2, 0.3, EdgeFormat::JsonLines, &mut jsonl)
+        .unwrap();
+    let parsed: Vec<serde_json::Value> = String::from_utf8(jsonl)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(parsed.len(), edges.len());
+    assert_eq!(parsed[0]["source"], edges[0].0);
+    assert_eq!(parsed[0]["target"], edges[0].1);
+    assert_eq!(
+        parsed[0]["score"].as_f64().unwrap().to_bits(),
+        edges[0].2.to_bits()
+    );
+
+    assert!(matcher.remove(1));
+    let after = matcher.knn_graph(2, 0.3);
+    assert!(
+        after
+            .iter()
+            .all(|&(source, target, _)| source != 1 && target != 1)
+    );
+}
+
+#[test]
+fn test_knn_graph_skips_zero_scores_and_repeats() {
+    // The "aaa" n-grams occur everywhere and weigh 0 under plain IDF; only "xyz" and "xyzw" share
+    // weighted features.
+    let haystack = ["aaa xyz", "aaa y", "aaa z", "aaa xyzw"];
+    let matcher = TFIDFMatcher::builder()
+        .idf(IdfMethod::Plain)
+        .build(haystack)
+        .expect("Failed to create matcher");
+    let edges = matcher.knn_graph(5, 0.0);
+    assert!(edges.iter().all(|edge| edge.2 > 0.0));
+    let mut pairs: Vec<(usize, usize)> = edges.iter().map(|edge| (edge.0, edge.1)).collect();
+    pairs.sort_unstable();
+    pairs.dedup();
+    assert_eq!(pairs.len(), edges.len());
+    assert_eq!(pairs, [(0, 3), (3, 0)]);
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_knn_graph_matches_sequential() {
+    let haystack: Vec<String> = (0..500)
+        .map(|i| format!("entry {} {}", i / 3, ["alpha", "beta", "gamma"][i % 3]))
+        .collect();
+    let matcher = TFIDFMatcher::new(haystack, 3).expect("Failed to create matcher");
+    let sequential = matcher.knn_graph(5, 0.2);
+    let parallel = matcher.par_knn_graph(5, 0.2);
+    assert_eq!(parallel.len(), sequential.len());
+    for (a, b) in sequential.iter().zip(&parallel) {
+        assert_eq!((a.0, a.1, a.2.to_bits()), (b.0, b.1, b.2.to_bits()));
+    }
+
+    let (mut seq_out, mut par_out) = (Vec::new(), Vec::new());
+    let written = matcher
+        .write_knn_graph(5, 0.2, EdgeFormat::JsonLines, &mut seq_out)
+        .unwrap();
+    let par_written = matcher
+        .par_write_knn_graph(5, 0.2, EdgeFormat::JsonLines, &mut par_out)
+        .unwrap();
+    assert_eq!((par_written, written), (sequential.len(), sequential.len()));
+    assert_eq!(par_out, seq_out);
+}
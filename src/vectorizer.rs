@@ -1,98 +1,490 @@
 //! A small, self-contained TF-IDF vectorizer.
 //!
-//! This replaces the previous dependency on `linfa-preprocessing`. The matcher only ever used a
-//! whitespace tokenizer with a `(1, 1)` n-gram range (the n-gram construction lives in
-//! [`crate::TFIDFMatcher::text_into_ngrams`]), so the vectorizer boils down to: build a
-//! token -> feature-index vocabulary, then map documents to a sparse TF-IDF matrix.
+//! This replaces the previous dependency on `linfa-preprocessing`. Documents arrive already
+//! tokenized (normalization and n-gram construction live in [`crate::analysis`]), so the
+//! vectorizer boils down to: build a token -> feature-index vocabulary, then map documents to a
+//! sparse TF-IDF matrix: each term count is transformed by a [`TfMethod`], then scaled by its
+//! token's weight and an [`IdfMethod`].
 //!
-//! The numerics reproduce linfa's defaults exactly so scores are unchanged:
-//! * document strings are NFKD-normalized then lowercased before tokenizing (`normalize` +
-//!   `convert_to_lowercase`);
-//! * the IDF uses the `Smooth` method, `ln((1 + n) / (1 + df)) + 1`;
-//! * crucially, IDF is recomputed per [`transform`](Vectorizer::transform) call from the batch
-//!   being transformed (both `n` and the document frequencies come from that batch), never stored
-//!   at fit time. Only the vocabulary is retained across calls — matching linfa's
-//!   `FittedTfIdfVectorizer`.
+//! [`fit`](Vectorizer::fit) also records the corpus document frequencies, so documents can be
+//! weighted two ways:
+//! * [`transform`](Vectorizer::transform) uses the IDF of the fitted corpus. A document's vector
+//!   then depends only on the document itself, which is what makes query scores independent of
+//!   how queries are batched ([`crate::ScoringVersion::V2`]).
+//! * [`transform_batch_idf`](Vectorizer::transform_batch_idf) recomputes the IDF from the batch
+//!   being transformed (both `n` and the document frequencies come from that batch), matching
+//!   linfa's `FittedTfIdfVectorizer` and the 0.2 scores ([`crate::ScoringVersion::V1`]).
+//!
+//! Transforming the fitted corpus itself yields the same matrix either way.
 //!
 //! Because everything is owned plain data (`HashMap`, `Vec`), the vectorizer — and therefore
 //! [`crate::TFIDFMatcher`] — is `Send + Sync`, which is what lets the matcher be shared across
 //! threads.
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use sprs::{CompressedStorage, CsMat, CsVec};
 use std::collections::HashMap;
-use unicode_normalization::UnicodeNormalization;
-
-/// Smooth inverse document frequency, matching linfa's `TfIdfMethod::Smooth`.
-// Document counts and frequencies are small integers; f64 represents them exactly here.
-#[allow(clippy::cast_precision_loss)]
-#[inline]
-fn smooth_idf(n: usize, df: usize) -> f64 {
-    ((1.0 + n as f64) / (1.0 + df as f64)).ln() + 1.0
+
+/// How inverse document frequencies are computed from the corpus size `n` and a feature's
+/// document frequency `df`. The first three variants mirror linfa's `TfIdfMethod`.
+///
+/// Every variant is clamped at zero so weights stay non-negative. Features can end up in no
+/// document once entries are removed; the methods dividing by `df` then count them as occurring
+/// once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum IdfMethod {
+    /// `ln((1 + n) / (1 + df)) + 1`, as if one extra document contained every feature.
+    #[default]
+    Smooth,
+    /// `ln(n / df) + 1`.
+    NonSmooth,
+    /// `ln(n / (1 + df))`.
+    Textbook,
+    /// `ln(n / df)`: features present in every document carry no weight.
+    Plain,
+    /// `ln((n - df) / df)`, the probabilistic IDF: features present in half or more of the
+    /// documents carry no weight.
+    Probabilistic,
+    /// `ln(1 + (n - df + 0.5) / (df + 0.5))`, the IDF of BM25; use it with [`TfMethod::Bm25`].
+    Bm25,
+}
+
+impl IdfMethod {
+    /// IDF weight of a feature found in `df` of `n` documents.
+    // Document counts and frequencies are small integers; f64 represents them exactly here.
+    #[allow(clippy::cast_precision_loss)]
+    #[inline]
+    pub(crate) fn idf(self, n: usize, df: usize) -> f64 {
+        let (n, df) = (n as f64, df as f64);
+        // Methods dividing by `df` treat an unseen feature as occurring once.
+        let seen = df.max(1.0);
+        let idf = match self {
+            Self::Smooth => ((1.0 + n) / (1.0 + df)).ln() + 1.0,
+            Self::NonSmooth => (n / seen).ln() + 1.0,
+            Self::Textbook => (n / (1.0 + df)).ln(),
+            Self::Plain => (n / seen).ln(),
+            Self::Probabilistic => ((n - seen) / seen).ln(),
+            Self::Bm25 => (1.0 + (n - df + 0.5) / (df + 0.5)).ln(),
+        };
+        idf.max(0.0)
+    }
 }
 
-/// NFKD-normalize then lowercase, matching linfa's `transform_string` with both `normalize` and
-/// `convert_to_lowercase` enabled (the defaults the matcher relied on).
-fn normalize_document(s: &str) -> String {
-    s.nfkd().collect::<String>().to_lowercase()
+/// How a token's count in a document is turned into its term frequency.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TfMethod {
+    /// The count itself.
+    #[default]
+    Raw,
+    /// `1 + ln(count)`, damping repeated tokens.
+    Sublinear,
+    /// 1 for every token present, however often.
+    Binary,
+    /// BM25 saturation with length normalization:
+    /// `count * (k1 + 1) / (count + k1 * (1 - b + b * len / avg_len))`, where `len` is the number
+    /// of tokens in the document and `avg_len` the average over the corpus. `k1` controls how
+    /// quickly repeated tokens saturate and `b` (between 0 and 1) how strongly long documents are
+    /// penalized. Needles are normalized against the corpus average too.
+    Bm25 {
+        /// Saturation parameter, typically 1.2 to 2.
+        k1: f64,
+        /// Length normalization strength, typically 0.75.
+        b: f64,
+    },
 }
 
-/// A fitted TF-IDF vectorizer holding only the learned vocabulary.
+impl TfMethod {
+    /// Term frequency of a token occurring `count` times in a document of `len` tokens, in a corpus
+    /// averaging `avg_len` tokens per document.
+    #[inline]
+    pub(crate) fn tf(self, count: f64, len: f64, avg_len: f64) -> f64 {
+        match self {
+            Self::Raw => count,
+            Self::Sublinear => 1.0 + count.ln(),
+            Self::Binary => 1.0,
+            Self::Bm25 { k1, b } => {
+                let relative_len = if avg_len > 0.0 { len / avg_len } else { 1.0 };
+                count * (k1 + 1.0) / (count + k1 * (1.0 - b + b * relative_len))
+            }
+        }
+    }
+}
+
+/// A raw term-frequency row: ascending feature indices and their counts.
+type TermRow = (Vec<usize>, Vec<f64>);
+
+/// A fitted TF-IDF vectorizer holding the learned vocabulary and corpus document frequencies.
 #[derive(Debug, Clone)]
 pub(crate) struct Vectorizer {
     /// Maps each token to its feature index. Insertion order defines the index; the exact ordering
     /// is irrelevant to cosine similarity as long as `fit` and `transform` agree.
     vocabulary: HashMap<String, usize>,
+    /// Number of documents in the fitted corpus.
+    n_docs: usize,
+    /// `doc_freqs[feature]` is the number of corpus documents containing that feature.
+    doc_freqs: Vec<usize>,
+    /// Total number of tokens over the corpus documents.
+    total_len: usize,
+    idf_method: IdfMethod,
+    tf_method: TfMethod,
 }
 
 impl Vectorizer {
-    /// Learns a vocabulary from `docs`. Each document is normalized and split on whitespace; every
-    /// distinct token becomes a feature.
-    pub(crate) fn fit<I, S>(docs: I) -> Self
+    /// Learns a vocabulary and document frequencies from tokenized `docs`; every distinct token
+    /// becomes a feature.
+    pub(crate) fn fit<I, D>(docs: I, idf_method: IdfMethod, tf_method: TfMethod) -> Self
     where
-        I: IntoIterator<Item = S>,
-        S: AsRef<str>,
+        I: IntoIterator<Item = D>,
+        D: AsRef<[String]>,
     {
         let mut vocabulary: HashMap<String, usize> = HashMap::new();
+        let mut doc_freqs: Vec<usize> = Vec::new();
+        // `last_seen[feature]` is the last document that counted towards that feature's frequency,
+        // so repeated tokens within one document are counted once.
+        let mut last_seen: Vec<usize> = Vec::new();
+        let mut n_docs = 0;
+        let mut total_len = 0;
         for doc in docs {
-            let normalized = normalize_document(doc.as_ref());
-            for token in normalized.split_whitespace() {
+            total_len += doc.as_ref().len();
+            for token in doc.as_ref() {
                 let next = vocabulary.len();
-                vocabulary.entry(token.to_owned()).or_insert(next);
+                let idx = *vocabulary.entry(token.clone()).or_insert(next);
+                if idx == doc_freqs.len() {
+                    doc_freqs.push(1);
+                    last_seen.push(n_docs);
+                } else if last_seen[idx] != n_docs {
+                    doc_freqs[idx] += 1;
+                    last_seen[idx] = n_docs;
+                }
             }
+            n_docs += 1;
+        }
+        Self {
+            vocabulary,
+            n_docs,
+            doc_freqs,
+            total_len,
+            idf_method,
+            tf_method,
         }
-        Self { vocabulary }
     }
 
-    /// Number of learned features (vocabulary size).
-    pub(crate) fn n_features(&self) -> usize {
+    /// Adds `doc` to the fitted corpus: unseen tokens become new features (appended after the
+    /// existing ones) and the document frequencies and corpus size are updated.
+    pub(crate) fn add_document(&mut self, doc: &[String]) {
+        let mut features: Vec<usize> = doc
+            .iter()
+            .map(|token| {
+                let next = self.vocabulary.len();
+                *self.vocabulary.entry(token.clone()).or_insert(next)
+            })
+            .collect();
+        self.doc_freqs.resize(self.vocabulary.len(), 0);
+        features.sort_unstable();
+        features.dedup();
+        for feature in features {
+            self.doc_freqs[feature] += 1;
+        }
+        self.n_docs += 1;
+        self.total_len += doc.len();
+    }
+
+    /// Removes `doc`, previously added by [`fit`](Self::fit) or
+    /// [`add_document`](Self::add_document), from the document frequencies and corpus size. Its
+    /// features stay in the vocabulary.
+    pub(crate) fn remove_document(&mut self, doc: &[String]) {
+        let mut features: Vec<usize> = doc
+            .iter()
+            .filter_map(|token| self.vocabulary.get(token).copied())
+            .collect();
+        features.sort_unstable();
+        features.dedup();
+        for feature in features {
+            self.doc_freqs[feature] = self.doc_freqs[feature].saturating_sub(1);
+        }
+        self.n_docs = self.n_docs.saturating_sub(1);
+        self.total_len = self.total_len.saturating_sub(doc.len());
+    }
+
+    /// Rebuilds a fitted vectorizer from persisted parts: the vocabulary terms in feature-index
+    /// order, the corpus size and token count, and per-feature document frequencies. Returns
+    /// `None` if a term repeats or the frequencies don't line up with the terms.
+    pub(crate) fn from_parts(
+        terms: Vec<String>,
+        n_docs: usize,
+        doc_freqs: Vec<usize>,
+        total_len: usize,
+        idf_method: IdfMethod,
+        tf_method: TfMethod,
+    ) -> Option<Self> {
+        if terms.len() != doc_freqs.len() {
+            return None;
+        }
+        let mut vocabulary = HashMap::with_capacity(terms.len());
+        for (idx, term) in terms.into_iter().enumerate() {
+            if vocabulary.insert(term, idx).is_some() {
+                return None;
+            }
+        }
+        Some(Self {
+            vocabulary,
+            n_docs,
+            doc_freqs,
+            total_len,
+            idf_method,
+            tf_method,
+        })
+    }
+
+    /// The vocabulary terms in feature-index order.
+    pub(crate) fn terms(&self) -> Vec<&str> {
+        let mut terms = vec![""; self.vocabulary.len()];
+        for (term, &idx) in &self.vocabulary {
+            terms[idx] = term;
+        }
+        terms
+    }
+
+    /// Per-feature document frequencies over the fitted corpus.
+    pub(crate) fn doc_freqs(&self) -> &[usize] {
+        &self.doc_freqs
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl Vectorizer {
+    /// Parallel counterpart of [`fit`](Self::fit), assigning the same feature indices. The corpus
+    /// is fitted in contiguous shards whose vocabularies are then merged in corpus order, so every
+    /// token still gets the index of its first occurrence.
+    pub(crate) fn par_fit<D>(docs: &[D], idf_method: IdfMethod, tf_method: TfMethod) -> Self
+    where
+        D: AsRef<[String]> + Sync,
+    {
+        let shard_len = docs.len().div_ceil(rayon::current_num_threads() * 4).max(1);
+        let shards: Vec<Self> = docs
+            .par_chunks(shard_len)
+            .map(|shard| Self::fit(shard, idf_method, tf_method))
+            .collect();
+
+        let mut merged = Self::fit(std::iter::empty::<&[String]>(), idf_method, tf_method);
+        for shard in shards {
+            let mut terms: Vec<(String, usize)> = shard.vocabulary.into_iter().collect();
+            terms.sort_unstable_by_key(|&(_, idx)| idx);
+            for (term, local) in terms {
+                let next = merged.vocabulary.len();
+                let idx = *merged.vocabulary.entry(term).or_insert(next);
+                if idx == merged.doc_freqs.len() {
+                    merged.doc_freqs.push(0);
+                }
+                merged.doc_freqs[idx] += shard.doc_freqs[local];
+            }
+            merged.n_docs += shard.n_docs;
+            merged.total_len += shard.total_len;
+        }
+        merged
+    }
+
+    /// Parallel counterpart of [`transform`](Vocabulary::transform), producing the same matrix.
+    /// Each document's tokens are counted by sorting them, so no per-thread scratch proportional to
+    /// the vocabulary is needed.
+    pub(crate) fn par_transform<D>(
+        &self,
+        docs: &[D],
+        weight: impl Fn(&str) -> f64 + Sync,
+    ) -> CsMat<f64>
+    where
+        D: AsRef<[String]> + Sync,
+    {
+        let (tf_method, avg_len) = (self.tf_method, self.avg_doc_len());
+        let rows: Vec<TermRow> = docs
+            .par_iter()
+            .map(|doc| {
+                let doc = doc.as_ref();
+                // Token counts are far below 2^52; the casts are exact in practice.
+                #[allow(clippy::cast_precision_loss)]
+                let len = doc.len() as f64;
+                let mut tokens: Vec<(usize, &str)> = doc
+                    .iter()
+                    .filter_map(|token| Some((self.feature(token)?, token.as_str())))
+                    .collect();
+                tokens.sort_unstable_by_key(|&(idx, _)| idx);
+
+                let mut row: TermRow = (Vec::new(), Vec::new());
+                for run in tokens.chunk_by(|a, b| a.0 == b.0) {
+                    let (idx, token) = run[0];
+                    #[allow(clippy::cast_precision_loss)]
+                    let count = run.len() as f64;
+                    let idf = self.idf_method.idf(self.n_docs, self.doc_freqs[idx]);
+                    row.0.push(idx);
+                    row.1
+                        .push(tf_method.tf(count, len, avg_len) * weight(token) * idf);
+                }
+                row
+            })
+            .collect();
+
+        let mut indptr = Vec::with_capacity(rows.len() + 1);
+        indptr.push(0);
+        let nnz = rows.iter().map(|(indices, _)| indices.len()).sum();
+        let (mut indices, mut data) = (Vec::with_capacity(nnz), Vec::with_capacity(nnz));
+        for (row_indices, row_values) in rows {
+            indices.extend(row_indices);
+            data.extend(row_values);
+            indptr.push(indices.len());
+        }
+        CsMat::new((indptr.len() - 1, self.n_features()), indptr, indices, data)
+    }
+}
+
+impl Vocabulary for Vectorizer {
+    fn feature(&self, token: &str) -> Option<usize> {
+        self.vocabulary.get(token).copied()
+    }
+
+    fn n_features(&self) -> usize {
         self.vocabulary.len()
     }
 
+    fn fitted_docs(&self) -> usize {
+        self.n_docs
+    }
+
+    fn doc_freq(&self, feature: usize) -> usize {
+        self.doc_freqs[feature]
+    }
+
+    fn total_len(&self) -> usize {
+        self.total_len
+    }
+
+    fn idf_method(&self) -> IdfMethod {
+        self.idf_method
+    }
+
+    fn tf_method(&self) -> TfMethod {
+        self.tf_method
+    }
+}
+
+/// Read access to a fitted vocabulary and its corpus statistics.
+///
+/// Implemented by the in-memory [`Vectorizer`] and by the memory-mapped index, which share the
+/// transform logic provided here.
+pub(crate) trait Vocabulary {
+    /// Feature index of `token`, if it is in the vocabulary.
+    fn feature(&self, token: &str) -> Option<usize>;
+    /// Number of learned features (vocabulary size).
+    fn n_features(&self) -> usize;
+    /// Number of documents in the fitted corpus.
+    fn fitted_docs(&self) -> usize;
+    /// Number of corpus documents containing `feature`.
+    fn doc_freq(&self, feature: usize) -> usize;
+    /// Total number of tokens over the fitted corpus.
+    fn total_len(&self) -> usize;
+    /// How document frequencies are turned into IDF weights.
+    fn idf_method(&self) -> IdfMethod;
+    /// How term counts are turned into term frequencies.
+    fn tf_method(&self) -> TfMethod;
+
+    /// Average number of tokens per fitted corpus document, 0 for an empty corpus.
+    // Token counts are far below 2^52; the casts are exact in practice.
+    #[allow(clippy::cast_precision_loss)]
+    fn avg_doc_len(&self) -> f64 {
+        match self.fitted_docs() {
+            0 => 0.0,
+            n => self.total_len() as f64 / n as f64,
+        }
+    }
+
+    /// Transforms `docs` into a `(n_docs, n_features)` sparse TF-IDF matrix (CSR), weighting every
+    /// document with the IDF of the fitted corpus. Each occurrence of a token counts
+    /// `weight(token)` towards its term frequency.
+    fn transform<I, D>(&self, docs: I, weight: impl Fn(&str) -> f64) -> CsMat<f64>
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[String]>,
+    {
+        let (rows, _) = self.term_counts(docs, weight);
+        let method = self.idf_method();
+        self.assemble(rows, |col| {
+            method.idf(self.fitted_docs(), self.doc_freq(col))
+        })
+    }
+
     /// Transforms `docs` into a `(n_docs, n_features)` sparse TF-IDF matrix (CSR).
     ///
     /// The IDF is derived from this batch: `n` is the number of documents passed in and each
     /// feature's document frequency is counted over those same documents.
-    pub(crate) fn transform<I, S>(&self, docs: I) -> CsMat<f64>
+    fn transform_batch_idf<I, D>(&self, docs: I, weight: impl Fn(&str) -> f64) -> CsMat<f64>
     where
-        I: IntoIterator<Item = S>,
-        S: AsRef<str>,
+        I: IntoIterator<Item = D>,
+        D: AsRef<[String]>,
+    {
+        let (rows, doc_freqs) = self.term_counts(docs, weight);
+        let n_docs = rows.len();
+        let method = self.idf_method();
+        self.assemble(rows, |col| method.idf(n_docs, doc_freqs[col]))
+    }
+
+    /// Sum of the TF-IDF weights the out-of-vocabulary tokens of `doc` would carry, each
+    /// weighted with the IDF of a feature found in no corpus document. Vectors drop these
+    /// tokens, so this is what their weight sum misses.
+    fn unseen_weight(&self, doc: &[String], weight: impl Fn(&str) -> f64) -> f64 {
+        let mut unseen: Vec<&str> = doc
+            .iter()
+            .map(String::as_str)
+            .filter(|token| self.feature(token).is_none())
+            .collect();
+        if unseen.is_empty() {
+            return 0.0;
+        }
+        unseen.sort_unstable();
+        let idf = self.idf_method().idf(self.fitted_docs(), 0);
+        let (tf_method, avg_len) = (self.tf_method(), self.avg_doc_len());
+        // Token counts are far below 2^52; the casts are exact in practice.
+        #[allow(clippy::cast_precision_loss)]
+        let len = doc.len() as f64;
+        unseen
+            .chunk_by(|a, b| a == b)
+            .map(|run| {
+                #[allow(clippy::cast_precision_loss)]
+                let count = run.len() as f64;
+                tf_method.tf(count, len, avg_len) * weight(run[0]) * idf
+            })
+            .sum()
+    }
+
+    /// Counts in-vocabulary tokens per document. Returns the weighted term-frequency rows (sorted
+    /// feature index -> term frequency times `weight(token)`) together with each feature's document
+    /// frequency over `docs`.
+    fn term_counts<I, D>(&self, docs: I, weight: impl Fn(&str) -> f64) -> (Vec<TermRow>, Vec<usize>)
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[String]>,
     {
         let n_features = self.n_features();
 
-        // Dense scratch for per-document term counts, reset sparsely via `touched`.
+        // Dense scratch for per-document term counts and token weights, reset sparsely via
+        // `touched`.
         let mut counts = vec![0usize; n_features];
+        let mut weights = vec![0.0; n_features];
         let mut doc_freqs = vec![0usize; n_features];
-        // Raw term-frequency rows (sorted feature index -> count), scaled by IDF once the whole
-        // batch has been seen.
-        let mut rows: Vec<(Vec<usize>, Vec<f64>)> = Vec::new();
+        let mut rows: Vec<TermRow> = Vec::new();
+        let (tf_method, avg_len) = (self.tf_method(), self.avg_doc_len());
 
         for doc in docs {
-            let normalized = normalize_document(doc.as_ref());
+            // Token counts are far below 2^52; the casts are exact in practice.
+            #[allow(clippy::cast_precision_loss)]
+            let len = doc.as_ref().len() as f64;
             let mut touched: Vec<usize> = Vec::new();
-            for token in normalized.split_whitespace() {
-                if let Some(&idx) = self.vocabulary.get(token) {
+            for token in doc.as_ref() {
+                if let Some(idx) = self.feature(token) {
                     if counts[idx] == 0 {
                         touched.push(idx);
+                        weights[idx] = weight(token);
                     }
                     counts[idx] += 1;
                 }
@@ -105,19 +497,24 @@ impl Vectorizer {
                 indices.push(idx);
                 // Term frequency is a small count; the f64 cast is exact in practice.
                 #[allow(clippy::cast_precision_loss)]
-                values.push(counts[idx] as f64);
+                let count = counts[idx] as f64;
+                values.push(tf_method.tf(count, len, avg_len) * weights[idx]);
                 doc_freqs[idx] += 1;
                 counts[idx] = 0; // reset in place; `touched` is dropped next iteration
             }
             rows.push((indices, values));
         }
+        (rows, doc_freqs)
+    }
 
-        let n_docs = rows.len();
+    /// Scales raw term-frequency rows by `idf(feature)` and stacks them into a CSR matrix.
+    fn assemble(&self, rows: Vec<TermRow>, idf: impl Fn(usize) -> f64) -> CsMat<f64> {
+        let n_features = self.n_features();
         let mut matrix = CsMat::empty(CompressedStorage::CSR, n_features);
-        matrix.reserve_outer_dim_exact(n_docs);
+        matrix.reserve_outer_dim_exact(rows.len());
         for (indices, mut values) in rows {
             for (value, &col) in values.iter_mut().zip(indices.iter()) {
-                *value *= smooth_idf(n_docs, doc_freqs[col]);
+                *value *= idf(col);
             }
             // `indices` is sorted ascending, satisfying CsVec's ordering invariant.
             let row = CsVec::new(n_features, indices, values);